@@ -23,8 +23,16 @@ struct Cli {
 enum Module {
     Ranks,
     Prices,
-    Products,
-    Stores,
+    Products {
+        #[command(subcommand)]
+        command: Option<products::Command>,
+    },
+    Stores {
+        #[command(flatten)]
+        args: stores::Args,
+        #[command(subcommand)]
+        command: Option<stores::Command>,
+    },
 }
 
 fn main() -> Result<()> {
@@ -32,7 +40,7 @@ fn main() -> Result<()> {
     match cli.module {
         Module::Ranks => ranks::main(),
         Module::Prices => prices::main(),
-        Module::Products => products::main(),
-        Module::Stores => stores::main(),
+        Module::Products { command } => products::main(command),
+        Module::Stores { args, command } => stores::main(command, args),
     }
 }
@@ -1,7 +1,7 @@
 use std::collections::{BTreeMap, BTreeSet};
-use std::fs::{read_dir, write, File};
+use std::fs::{read_dir, read_to_string, write, File};
 use std::io::{BufRead, BufReader, Write};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use anyhow::{Context, Result};
 use indicatif::ProgressBar;
@@ -123,6 +123,30 @@ pub fn main() -> Result<()> {
     Ok(())
 }
 
+/// Snapshot names with a price index for the vendor, oldest first.
+pub fn snapshots(vendor: Vendor) -> Result<Vec<String>> {
+    let suffix = format!("-{}.json", vendor.slug());
+    let mut output = Vec::new();
+    if !Path::new("data/prices").exists() {
+        return Ok(output);
+    }
+    for x in read_dir("data/prices")? {
+        let file = x?.file_name();
+        if let Some(name) = file.to_string_lossy().strip_suffix(&suffix) {
+            output.push(name.to_string());
+        }
+    }
+    output.sort();
+    Ok(output)
+}
+
+pub fn load_index(name: &str, vendor: Vendor) -> Result<RawPriceIndex> {
+    let path = output_path(vendor, name)
+        .with_extension("")
+        .with_extension("json");
+    Ok(serde_json::from_str(&read_to_string(path)?)?)
+}
+
 pub fn load(name: &str, vendor: Vendor) -> Result<RawPrices> {
     let data = zstd::decode_all(File::open(format!(
         "data/prices/{name}-{}.bin.zst",
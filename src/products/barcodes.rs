@@ -0,0 +1,235 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Write,
+    fs::{self, File},
+    io::{BufRead, BufReader},
+    path::Path,
+};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::Vendor;
+
+use super::{gtin::Gtin, models::ProductId};
+
+pub(super) const OUTPUT_PATH: &str = "data/products/matches.jsonl";
+
+pub fn main() -> Result<()> {
+    let mut barcodes: BTreeMap<Gtin, BTreeSet<ProductId>> = BTreeMap::new();
+    let mut invalid = String::new();
+    let mut stats = String::new();
+    for vendor in Vendor::all() {
+        let mut count = 0usize;
+        let mut excluded = 0usize;
+        let mut errors = Vec::new();
+        for (id, eans) in load(vendor)? {
+            count += 1;
+            for ean in eans {
+                match ean.parse::<Gtin>() {
+                    Ok(x) if x.is_global() => {
+                        barcodes.entry(x).or_default().insert(id);
+                    }
+                    Ok(_) => excluded += 1,
+                    Err(e) => errors.push(format!("- {id:?}: {e}")),
+                }
+            }
+        }
+        eprintln!("Loaded {count} {vendor} products");
+        writeln!(
+            stats,
+            "- {vendor}: {count} products, {} invalid and {excluded} in-store barcodes",
+            errors.len()
+        )?;
+        if !errors.is_empty() {
+            writeln!(invalid, "### {vendor}\n")?;
+            writeln!(invalid, "{}\n", errors.join("\n"))?;
+        }
+    }
+
+    let (matches, todo) = build(&barcodes)?;
+
+    let mut md = String::new();
+    writeln!(md, "## Statistics\n")?;
+    write!(md, "{stats}")?;
+    writeln!(md, "- {} barcodes", barcodes.len())?;
+    writeln!(md, "- {} matched products", matches.len())?;
+    writeln!(md)?;
+    if !todo.is_empty() {
+        writeln!(md, "## Todo\n")?;
+        writeln!(md, "{todo}")?;
+    }
+    if !invalid.is_empty() {
+        writeln!(md, "## Invalid barcodes\n")?;
+        write!(md, "{invalid}")?;
+    }
+    fs::write(Path::new(OUTPUT_PATH).with_extension("md"), md)?;
+
+    let mut contents = String::new();
+    for x in &matches {
+        contents.push_str(&serde_json::to_string(x)?);
+        contents.push('\n');
+    }
+    fs::write(OUTPUT_PATH, contents)?;
+    eprintln!("Matched {} products", matches.len());
+
+    Ok(())
+}
+
+/// Loads the persisted match table written by `products match`.
+pub fn load_matches() -> Result<Vec<Match>> {
+    let mut output = Vec::new();
+    for line in fs::read_to_string(OUTPUT_PATH)?.lines() {
+        output.push(serde_json::from_str(line)?);
+    }
+    Ok(output)
+}
+
+/// Product barcodes from the retailer catalogue dumps, the same ones the
+/// `barcodes` crate reads.
+fn load(vendor: Vendor) -> Result<Vec<(ProductId, Vec<String>)>> {
+    let path = format!("raw/{}.jsonl.zst", vendor.slug());
+    let reader = BufReader::new(zstd::Decoder::new(
+        File::open(&path).with_context(|| format!("Failed to open {path}"))?,
+    )?);
+
+    let mut output = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        let raw: RawProduct =
+            serde_json::from_str(&line).with_context(|| format!("Failed to load: {line}"))?;
+        if let Some(id) = vendor.parse_product_id(&raw.id) {
+            if let ProductId::Woolworths(x) = id {
+                if x >= 1_000_000_000 {
+                    // everyday market
+                    continue;
+                }
+            }
+            output.push((id, raw.eans));
+        }
+    }
+    Ok(output)
+}
+
+/// Pairs products that share a barcode, reporting barcodes that are ambiguous
+/// within a retailer and products that pair with more than one counterpart.
+fn build(barcodes: &BTreeMap<Gtin, BTreeSet<ProductId>>) -> Result<(Vec<Match>, String)> {
+    let mut todo = String::new();
+    let mut pairs: BTreeMap<(u32, u32), Vec<Gtin>> = BTreeMap::new();
+    for (barcode, products) in barcodes {
+        let coles: Vec<u32> = products.iter().filter_map(|x| x.coles()).collect();
+        let woolworths: Vec<u32> = products.iter().filter_map(|x| x.woolworths()).collect();
+        match (coles.as_slice(), woolworths.as_slice()) {
+            ([], _) | (_, []) => (),
+            ([coles], [woolworths]) => pairs
+                .entry((*coles, *woolworths))
+                .or_default()
+                .push(barcode.clone()),
+            _ => writeln!(todo, "- {barcode} is shared by {products:?}")?,
+        }
+    }
+
+    // a product should only ever pair with one counterpart
+    let mut counterparts: BTreeMap<ProductId, BTreeSet<ProductId>> = BTreeMap::new();
+    for (coles, woolworths) in pairs.keys() {
+        let (coles, woolworths) = (ProductId::Coles(*coles), ProductId::Woolworths(*woolworths));
+        counterparts.entry(coles).or_default().insert(woolworths);
+        counterparts.entry(woolworths).or_default().insert(coles);
+    }
+    let mut conflicted = BTreeSet::new();
+    for (id, others) in &counterparts {
+        if others.len() > 1 {
+            writeln!(todo, "- {id:?} matches {others:?}")?;
+            conflicted.insert(*id);
+        }
+    }
+
+    let matches = pairs
+        .into_iter()
+        .filter(|((coles, woolworths), _)| {
+            !conflicted.contains(&ProductId::Coles(*coles))
+                && !conflicted.contains(&ProductId::Woolworths(*woolworths))
+        })
+        .map(|((coles, woolworths), barcodes)| Match {
+            coles,
+            woolworths,
+            barcodes,
+        })
+        .collect();
+
+    Ok((matches, todo))
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Match {
+    pub coles: u32,
+    pub woolworths: u32,
+    pub barcodes: Vec<Gtin>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct RawProduct {
+    id: String,
+    #[serde(rename = "EANs")]
+    eans: Vec<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gtin(s: &str) -> Gtin {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn matches_and_conflicts() {
+        let barcodes = BTreeMap::from_iter(
+            [
+                (
+                    "9300000000019",
+                    vec![ProductId::Coles(1), ProductId::Woolworths(10)],
+                ),
+                (
+                    "9300000000026",
+                    vec![ProductId::Coles(1), ProductId::Woolworths(10)],
+                ),
+                // only at one retailer
+                ("9300000000033", vec![ProductId::Coles(2)]),
+                // ambiguous within coles
+                (
+                    "9300000000040",
+                    vec![
+                        ProductId::Coles(3),
+                        ProductId::Coles(4),
+                        ProductId::Woolworths(30),
+                    ],
+                ),
+                // coles 5 pairs with two woolworths products
+                (
+                    "9300000000057",
+                    vec![ProductId::Coles(5), ProductId::Woolworths(50)],
+                ),
+                (
+                    "9300000000064",
+                    vec![ProductId::Coles(5), ProductId::Woolworths(51)],
+                ),
+            ]
+            .map(|(k, v)| (gtin(k), BTreeSet::from_iter(v))),
+        );
+
+        let (matches, todo) = build(&barcodes).unwrap();
+        assert_eq!(
+            matches,
+            vec![Match {
+                coles: 1,
+                woolworths: 10,
+                barcodes: vec![gtin("9300000000019"), gtin("9300000000026")],
+            }]
+        );
+        assert_eq!(todo.lines().count(), 2);
+        assert!(todo.contains("09300000000040 is shared by"));
+        assert!(todo.contains("[Coles Product 5]"));
+    }
+}
@@ -0,0 +1,382 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::Path,
+};
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use super::{
+    barcodes, fuzzy,
+    models::ProductId,
+    size::{self, Size},
+    tokens::Tokenizer,
+    UpstreamProduct,
+};
+
+pub const CATALOGUE_PATH: &str = "data/products/catalogue.yaml";
+
+pub fn check(tokenizer: &Tokenizer) -> Result<()> {
+    let catalogue = Catalogue::load(Path::new(CATALOGUE_PATH))?;
+    let problems = catalogue.validate(tokenizer);
+    for problem in &problems {
+        println!("{CATALOGUE_PATH}: {problem}");
+    }
+
+    if !problems.is_empty() {
+        bail!("{} problems in {CATALOGUE_PATH}", problems.len());
+    }
+    eprintln!(
+        "No problems in {CATALOGUE_PATH} ({} products)",
+        catalogue.products.len()
+    );
+
+    Ok(())
+}
+
+pub fn format(tokenizer: &Tokenizer) -> Result<()> {
+    let mut catalogue = Catalogue::load(Path::new(CATALOGUE_PATH))?;
+    catalogue.complete(tokenizer);
+    catalogue.write(Path::new(CATALOGUE_PATH))
+}
+
+pub fn update(tokenizer: &Tokenizer, upstream: &[UpstreamProduct]) -> Result<()> {
+    let mut catalogue = Catalogue::load(Path::new(CATALOGUE_PATH))?;
+    let linked: BTreeSet<ProductId> = catalogue.products.iter().flat_map(|x| x.links()).collect();
+    let upstream: BTreeMap<ProductId, &UpstreamProduct> = upstream
+        .iter()
+        .map(|x| (ProductId::new(x.vendor, x.id), x))
+        .collect();
+
+    let mut pairs: Vec<(u32, u32)> = Vec::new();
+    if Path::new(barcodes::OUTPUT_PATH).exists() {
+        pairs.extend(
+            barcodes::load_matches()?
+                .into_iter()
+                .map(|x| (x.coles, x.woolworths)),
+        );
+    }
+    if Path::new(fuzzy::OUTPUT_PATH).exists() {
+        pairs.extend(
+            fuzzy::load_matches()?
+                .into_iter()
+                .map(|x| (x.coles, x.woolworths)),
+        );
+    }
+
+    let mut added = 0usize;
+    for (coles, woolworths) in pairs {
+        let (coles, woolworths) = (ProductId::Coles(coles), ProductId::Woolworths(woolworths));
+        if linked.contains(&coles) || linked.contains(&woolworths) {
+            continue;
+        }
+        // products outside the ranked set have no name or size to use, and
+        // Coles names leave out the brand so Woolworths is preferred
+        let Some(product) = upstream.get(&woolworths).or_else(|| upstream.get(&coles)) else {
+            continue;
+        };
+        let Some(size) = product.size.clone() else {
+            continue;
+        };
+        catalogue.products.push(Product {
+            id: String::new(),
+            name: full_name(product),
+            size,
+            category: None,
+            image: None,
+            coles: vec![coles.coles().unwrap()],
+            woolworths: vec![woolworths.woolworths().unwrap()],
+        });
+        added += 1;
+    }
+
+    catalogue.complete(tokenizer);
+    catalogue.write(Path::new(CATALOGUE_PATH))?;
+    eprintln!("Added {added} products to {CATALOGUE_PATH}");
+
+    Ok(())
+}
+
+fn full_name(product: &UpstreamProduct) -> String {
+    let (brand, name) = (product.brand.trim(), product.name.trim());
+    if brand.is_empty() || name.to_lowercase().starts_with(&brand.to_lowercase()) {
+        name.to_string()
+    } else {
+        format!("{brand} {name}")
+    }
+}
+
+/// A curated product, the same item across retailers.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Product {
+    /// Stable once assigned, even if the name changes
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub id: String,
+    pub name: String,
+    #[serde(
+        serialize_with = "size_to_string",
+        deserialize_with = "size_from_string"
+    )]
+    pub size: Size,
+    /// The most specific token the product belongs to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub category: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image: Option<Image>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub coles: Vec<u32>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub woolworths: Vec<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Image {
+    pub url: String,
+    /// SHA-256 of the image
+    pub hash: String,
+}
+
+impl Product {
+    pub fn links(&self) -> impl Iterator<Item = ProductId> + '_ {
+        self.coles
+            .iter()
+            .map(|x| ProductId::Coles(*x))
+            .chain(self.woolworths.iter().map(|x| ProductId::Woolworths(*x)))
+    }
+
+    /// The id a new product gets, e.g. `coca-cola-classic-1-25l`.
+    fn default_id(&self) -> String {
+        let mut id = String::new();
+        for x in format!("{} {}", self.name, self.size).chars() {
+            if x.is_ascii_alphanumeric() {
+                id.push(x.to_ascii_lowercase());
+            } else if !id.is_empty() && !id.ends_with('-') {
+                id.push('-');
+            }
+        }
+        id.trim_end_matches('-').to_string()
+    }
+}
+
+fn size_to_string<S: Serializer>(size: &Size, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(size)
+}
+
+fn size_from_string<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Size, D::Error> {
+    let s = String::deserialize(deserializer)?;
+    size::parse(&s).ok_or_else(|| serde::de::Error::custom(format!("invalid size {s}")))
+}
+
+#[derive(Debug, Default)]
+pub struct Catalogue {
+    pub products: Vec<Product>,
+}
+
+impl Catalogue {
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        Self::parse(&fs::read_to_string(path)?)
+            .with_context(|| format!("Failed to load {}", path.display()))
+    }
+
+    pub fn parse(yaml: &str) -> Result<Self> {
+        Ok(Self {
+            products: serde_yaml::from_str::<Option<Vec<Product>>>(yaml)?.unwrap_or_default(),
+        })
+    }
+
+    // sorted, so the file is the same however it was edited
+    pub fn write(&self, path: &Path) -> Result<()> {
+        fs::write(path, self.to_yaml()?)?;
+        Ok(())
+    }
+
+    fn to_yaml(&self) -> Result<String> {
+        let mut products = self.products.clone();
+        for x in &mut products {
+            x.coles.sort();
+            x.coles.dedup();
+            x.woolworths.sort();
+            x.woolworths.dedup();
+        }
+        products.sort_by(|a, b| a.id.cmp(&b.id));
+        Ok(serde_yaml::to_string(&products)?)
+    }
+
+    fn complete(&mut self, tokenizer: &Tokenizer) {
+        let mut ids: BTreeSet<String> = self.products.iter().map(|x| x.id.clone()).collect();
+        for x in &mut self.products {
+            if x.id.is_empty() {
+                let base = x.default_id();
+                let mut id = base.clone();
+                let mut n = 2;
+                while ids.contains(&id) {
+                    id = format!("{base}-{n}");
+                    n += 1;
+                }
+                ids.insert(id.clone());
+                x.id = id;
+            }
+            if x.category.is_none() {
+                x.category = tokenizer
+                    .classify("", &x.name)
+                    .ok()
+                    .and_then(|path| path.last().map(|x| x.to_string()));
+            }
+        }
+    }
+
+    pub fn validate(&self, tokenizer: &Tokenizer) -> Vec<String> {
+        let tokens: BTreeSet<&str> = tokenizer.tokens().map(|(slug, _, _)| slug).collect();
+        let mut problems = Vec::new();
+        let mut ids = BTreeSet::new();
+        let mut links: BTreeMap<ProductId, &str> = BTreeMap::new();
+        for x in &self.products {
+            let id = x.id.as_str();
+            if id.is_empty() {
+                problems.push(format!(
+                    "{} {} has no id, run `products catalogue format`",
+                    x.name, x.size
+                ));
+            } else if !id
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+            {
+                problems.push(format!("{id}: invalid id"));
+            } else if !ids.insert(id) {
+                problems.push(format!("{id}: duplicate id"));
+            }
+            if x.name.trim().is_empty() {
+                problems.push(format!("{id}: no name"));
+            }
+            if let Some(category) = &x.category {
+                if !tokens.contains(category.as_str()) {
+                    problems.push(format!("{id}: unknown category {category}"));
+                }
+            }
+            if x.coles.is_empty() && x.woolworths.is_empty() {
+                problems.push(format!("{id}: no retailer products"));
+            }
+            for link in x.links() {
+                if let Some(other) = links.insert(link, id) {
+                    if other != id {
+                        problems.push(format!("{id}: {link:?} is already linked to {other}"));
+                    }
+                }
+            }
+        }
+        problems
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const YAML: &str = r#"
+- name: Coca-Cola Classic
+  size: 4 x 330ml
+  woolworths: [290133]
+  coles: [3448271]
+- id: coca-cola-classic-1-25l
+  name: Coca-Cola Classic
+  size: 1.25 l
+  category: cola
+  coles: [123011]
+  woolworths: [32731, 32731]
+"#;
+
+    fn tokenizer() -> Tokenizer {
+        Tokenizer::parse("- Drink\n- Soft+Drink .drink\n- Cola .soft-drink\n- Coca-Cola .cola\n")
+            .unwrap()
+    }
+
+    #[test]
+    fn canonical() {
+        let mut catalogue = Catalogue::parse(YAML).unwrap();
+        assert_eq!(catalogue.validate(&tokenizer()).len(), 1);
+        catalogue.complete(&tokenizer());
+        assert!(catalogue.validate(&tokenizer()).is_empty());
+
+        let yaml = catalogue.to_yaml().unwrap();
+        assert_eq!(
+            yaml,
+            r#"- id: coca-cola-classic-1-25l
+  name: Coca-Cola Classic
+  size: 1.25L
+  category: cola
+  coles:
+  - 123011
+  woolworths:
+  - 32731
+- id: coca-cola-classic-4-x-330ml
+  name: Coca-Cola Classic
+  size: 4 x 330mL
+  category: coca-cola
+  coles:
+  - 3448271
+  woolworths:
+  - 290133
+"#
+        );
+        // formatting is stable
+        assert_eq!(Catalogue::parse(&yaml).unwrap().to_yaml().unwrap(), yaml);
+    }
+
+    #[test]
+    fn problems() {
+        let catalogue = Catalogue::parse(
+            r#"
+- id: Coke
+  name: Coca-Cola
+  size: 1.25L
+  category: soda
+- id: coke-2l
+  name: Coca-Cola
+  size: 2L
+  coles: [1]
+- id: coke-2l
+  name: ""
+  size: 2L
+  coles: [1]
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            catalogue.validate(&tokenizer()),
+            vec![
+                "Coke: invalid id",
+                "Coke: unknown category soda",
+                "Coke: no retailer products",
+                "coke-2l: duplicate id",
+                "coke-2l: no name",
+            ]
+        );
+        assert!(Catalogue::parse("- name: Coca-Cola\n  size: large\n").is_err());
+    }
+
+    #[test]
+    fn names() {
+        use crate::{products::upstream, Vendor};
+
+        let coles = upstream(Vendor::Coles, 1, "Dairy Farmers", "Full Cream Milk", "2L");
+        assert_eq!(full_name(&coles), "Dairy Farmers Full Cream Milk");
+        let woolworths = upstream(
+            Vendor::Woolworths,
+            2,
+            "Dairy Farmers",
+            "Dairy Farmers Full Cream Milk",
+            "2L",
+        );
+        assert_eq!(full_name(&woolworths), "Dairy Farmers Full Cream Milk");
+
+        let image = "- name: Milk\n  size: 2L\n  image:\n    url: https://example.com/1.jpg\n    hash: ab\n  coles: [1]\n";
+        let catalogue = Catalogue::parse(image).unwrap();
+        assert_eq!(catalogue.products[0].image.as_ref().unwrap().hash, "ab");
+    }
+}
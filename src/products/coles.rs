@@ -1,5 +1,4 @@
 use std::{
-    collections::BTreeMap,
     fs::File,
     io::{BufRead, BufReader},
 };
@@ -9,69 +8,41 @@ use serde::Deserialize;
 
 use crate::Vendor;
 
-use super::UpstreamProduct;
-
-// use super::size::{self, Size};
+use super::{
+    nutrition::{Nutrition, RawBreakdown},
+    size::{self, Coverage, Size, Unit},
+    UpstreamProduct,
+};
 
 pub fn load() -> Result<Vec<UpstreamProduct>> {
     let mut output = Vec::new();
-    let mut reader = BufReader::new(File::open("internal/coles-products/raw.jsonl")?);
+    let mut coverage = Coverage::default();
+    let reader = BufReader::new(File::open("internal/coles-products/raw.jsonl")?);
     for result in reader.lines() {
         let line = result?;
         let raw: RawProduct =
             serde_json::from_str(&line).with_context(|| format!("Failed to load: {line}"))?;
 
-        // let pack: Option<u32> = raw
-        //     .size
-        //     .strip_suffix(" Pack")
-        //     .or(raw.size.strip_suffix(" pack"))
-        //     .and_then(|x| x.parse().ok());
-
-        // if let Some(pack) = pack {
-        //     if let Some((amount, unit)) = raw
-        //         .nutrition
-        //         .as_ref()
-        //         .and_then(|x| size::find_amount(x.serving_size))
-        //     {
-        //         Size {
-        //             pack,
-        //             amount_is_total: false,
-        //             amount,
-        //             unit,
-        //         }
-        //     } else {
-        //         Size {
-        //             pack: 1,
-        //             amount_is_total: false,
-        //             amount: pack,
-        //             unit:
-
-        //         }
-
-        //     }
-        // }
-
-        // let nutrition_pack: Option<f32> = raw
-        //     .nutrition
-        //     .as_ref()
-        //     .and_then(|x| x.servings_per_package.parse().ok());
-
-        // if let Some(pack) = pack {
-        //     if let Some(x) = nutrition_pack {
-        //         dbg!(raw);
-        //     }
-        // }
-
-        // let size = match pack {
-        //     Some(pack) => if let Some(x) = raw.nutrition {
-        //         if
-        //     }
-
-        // }
-
-        // if let Some((a, u)) = size::find_amount(&raw.size) {}
-
-        // output.insert(raw.id, raw);
+        // multipacks often only list a count, so use the nutrition panel for
+        // the total amount where possible
+        let servings = raw
+            .nutrition
+            .as_ref()
+            .and_then(|x| size::from_servings(&x.serving_size, &x.servings_per_package));
+        let size = match (size::parse(&raw.size), servings) {
+            (Some(x), Some(servings)) if x.unit == Unit::Pack => Some(Size {
+                pack: x.pack,
+                amount_is_total: true,
+                ..servings
+            }),
+            (None, servings) => servings,
+            (size, _) => size,
+        };
+        coverage.record(&raw.size, &size);
+        let nutrition = raw
+            .nutrition
+            .as_ref()
+            .and_then(|x| Nutrition::parse(&x.serving_size, &x.breakdown));
 
         output.push(UpstreamProduct {
             vendor: Vendor::Coles,
@@ -79,9 +50,11 @@ pub fn load() -> Result<Vec<UpstreamProduct>> {
             brand: raw.brand,
             name: raw.name,
             description: raw.long_description.unwrap_or_default(),
-            size: None,
+            size,
+            nutrition,
         })
     }
+    coverage.write(Vendor::Coles)?;
 
     Ok(output)
 }
@@ -102,4 +75,6 @@ struct RawProduct {
 struct RawNutrition {
     serving_size: String,
     servings_per_package: String,
+    #[serde(default)]
+    breakdown: Vec<RawBreakdown>,
 }
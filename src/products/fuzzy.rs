@@ -0,0 +1,336 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Write,
+    fs,
+    path::Path,
+};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::Vendor;
+
+use super::{
+    barcodes,
+    models::ProductId,
+    size::Size,
+    tokens::{normalize, Tokenizer},
+    UpstreamProduct,
+};
+
+pub(super) const OUTPUT_PATH: &str = "data/products/fuzzy.jsonl";
+const REVIEW_PATH: &str = "data/products/review.md";
+
+// relative difference between two sizes of the same dimension
+const SIZE_TOLERANCE: f32 = 0.02;
+
+pub fn main(
+    tokenizer: &Tokenizer,
+    products: &[UpstreamProduct],
+    threshold: f64,
+    review: f64,
+) -> Result<()> {
+    // barcode matches win, so those products are never considered here
+    let mut matched = BTreeSet::new();
+    if Path::new(barcodes::OUTPUT_PATH).exists() {
+        for x in barcodes::load_matches()? {
+            matched.insert(ProductId::Coles(x.coles));
+            matched.insert(ProductId::Woolworths(x.woolworths));
+        }
+    } else {
+        eprintln!("No barcode matches, run `products match` first to include them");
+    }
+
+    let mut coles = Vec::new();
+    let mut woolworths = Vec::new();
+    for product in products {
+        if matched.contains(&ProductId::new(product.vendor, product.id)) {
+            continue;
+        }
+        let x = Features::new(tokenizer, product);
+        match product.vendor {
+            Vendor::Coles => coles.push(x),
+            Vendor::Woolworths => woolworths.push(x),
+        }
+    }
+
+    let pairs = pair(&coles, &woolworths, review);
+    let (accepted, queued): (Vec<_>, Vec<_>) =
+        pairs.into_iter().partition(|x| x.score.total >= threshold);
+
+    let mut contents = String::new();
+    for x in &accepted {
+        contents.push_str(&serde_json::to_string(&FuzzyMatch {
+            coles: x.coles.product.id,
+            woolworths: x.woolworths.product.id,
+            score: x.score.total,
+        })?);
+        contents.push('\n');
+    }
+    fs::write(OUTPUT_PATH, contents)?;
+
+    let mut md = String::new();
+    writeln!(md, "## Statistics\n")?;
+    writeln!(
+        md,
+        "- {} products already matched by barcode",
+        matched.len()
+    )?;
+    writeln!(md, "- {} matches above {threshold}", accepted.len())?;
+    writeln!(md, "- {} matches to review", queued.len())?;
+    writeln!(md)?;
+    if !queued.is_empty() {
+        writeln!(md, "## Review\n")?;
+        for x in &queued {
+            writeln!(md, "{}", x.line())?;
+        }
+    }
+    fs::write(REVIEW_PATH, md)?;
+    eprintln!(
+        "Matched {} products, {} to review",
+        accepted.len(),
+        queued.len()
+    );
+
+    Ok(())
+}
+
+pub fn load_matches() -> Result<Vec<FuzzyMatch>> {
+    let mut output = Vec::new();
+    for line in fs::read_to_string(OUTPUT_PATH)?.lines() {
+        output.push(serde_json::from_str(line)?);
+    }
+    Ok(output)
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct FuzzyMatch {
+    pub coles: u32,
+    pub woolworths: u32,
+    pub score: f64,
+}
+
+struct Features<'a> {
+    product: &'a UpstreamProduct,
+    brand: String,
+    path: Vec<&'a str>,
+    words: BTreeSet<String>,
+}
+
+impl<'a> Features<'a> {
+    fn new(tokenizer: &'a Tokenizer, product: &'a UpstreamProduct) -> Self {
+        let brand = normalize(&product.brand).trim().to_string();
+        let brand_words: BTreeSet<&str> = brand.split_whitespace().collect();
+        let words = normalize(&product.name)
+            .split_whitespace()
+            .filter(|x| !brand_words.contains(x) && !x.contains(|c: char| c.is_ascii_digit()))
+            .map(String::from)
+            .collect();
+        Self {
+            product,
+            brand,
+            path: tokenizer
+                .classify(&product.brand, &product.name)
+                .unwrap_or_default(),
+            words,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Score {
+    brand: f64,
+    classification: f64,
+    size: f64,
+    name: f64,
+    total: f64,
+}
+
+impl Score {
+    // `None` if both sizes are known and differ
+    fn new(a: &Features, b: &Features) -> Option<Self> {
+        let size = size(a.product.size.as_ref(), b.product.size.as_ref())?;
+        // only near-identical spellings count, private labels never match
+        let brand = match strsim::jaro_winkler(&a.brand, &b.brand) {
+            _ if a.brand.is_empty() || b.brand.is_empty() => 0.0,
+            x if x >= 0.9 => x,
+            _ => 0.0,
+        };
+        let classification = match (a.path.as_slice(), b.path.as_slice()) {
+            ([], _) | (_, []) => 0.5,
+            (x, y) => {
+                let common = x.iter().zip(y).take_while(|(x, y)| x == y).count();
+                common as f64 / x.len().max(y.len()) as f64
+            }
+        };
+        let name = match a.words.union(&b.words).count() {
+            0 => 0.0,
+            union => a.words.intersection(&b.words).count() as f64 / union as f64,
+        };
+
+        Some(Self {
+            brand,
+            classification,
+            size,
+            name,
+            total: 0.3 * brand + 0.2 * classification + 0.2 * size + 0.3 * name,
+        })
+    }
+}
+
+fn size(a: Option<&Size>, b: Option<&Size>) -> Option<f64> {
+    let (Some(a), Some(b)) = (a, b) else {
+        return Some(0.5);
+    };
+    let (a_dimension, a) = a.comparable();
+    let (b_dimension, b) = b.comparable();
+    (a_dimension == b_dimension && (a - b).abs() <= a.max(b) * SIZE_TOLERANCE).then_some(1.0)
+}
+
+struct Pair<'a> {
+    coles: &'a Features<'a>,
+    woolworths: &'a Features<'a>,
+    score: Score,
+}
+
+impl Pair<'_> {
+    fn line(&self) -> String {
+        let Score {
+            brand,
+            classification,
+            size,
+            name,
+            total,
+        } = self.score;
+        format!(
+            "- {total:.2} {:?} {} ↔ {:?} {} (brand {brand:.2}, class {classification:.2}, size {size:.2}, name {name:.2})",
+            ProductId::Coles(self.coles.product.id),
+            self.coles.product.name,
+            ProductId::Woolworths(self.woolworths.product.id),
+            self.woolworths.product.name,
+        )
+    }
+}
+
+/// Greedily pairs the highest scoring candidates sharing a brand or
+/// classification, each product at most once.
+fn pair<'a>(
+    coles: &'a [Features<'a>],
+    woolworths: &'a [Features<'a>],
+    min_score: f64,
+) -> Vec<Pair<'a>> {
+    let mut by_brand: BTreeMap<&str, Vec<usize>> = BTreeMap::new();
+    let mut by_class: BTreeMap<&str, Vec<usize>> = BTreeMap::new();
+    for (i, x) in woolworths.iter().enumerate() {
+        if !x.brand.is_empty() {
+            by_brand.entry(&x.brand).or_default().push(i);
+        }
+        if let Some(leaf) = x.path.last() {
+            by_class.entry(leaf).or_default().push(i);
+        }
+    }
+
+    let mut candidates = Vec::new();
+    for (i, x) in coles.iter().enumerate() {
+        let others: BTreeSet<usize> = by_brand
+            .get(x.brand.as_str())
+            .into_iter()
+            .chain(x.path.last().and_then(|x| by_class.get(x)))
+            .flatten()
+            .copied()
+            .collect();
+        for j in others {
+            if let Some(score) = Score::new(x, &woolworths[j]) {
+                if score.total >= min_score {
+                    candidates.push((i, j, score));
+                }
+            }
+        }
+    }
+    candidates.sort_by(|a, b| {
+        b.2.total
+            .total_cmp(&a.2.total)
+            .then((a.0, a.1).cmp(&(b.0, b.1)))
+    });
+
+    let mut used_coles = BTreeSet::new();
+    let mut used_woolworths = BTreeSet::new();
+    let mut output = Vec::new();
+    for (i, j, score) in candidates {
+        if used_coles.contains(&i) || used_woolworths.contains(&j) {
+            continue;
+        }
+        used_coles.insert(i);
+        used_woolworths.insert(j);
+        output.push(Pair {
+            coles: &coles[i],
+            woolworths: &woolworths[j],
+            score,
+        });
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::products::upstream as product;
+
+    #[test]
+    fn pairs() {
+        let tokenizer = Tokenizer::parse(
+            r#"
+- Drink
+- Juice .drink
+- Orange +juice
+- Apple +juice
+"#,
+        )
+        .unwrap();
+        let coles = [
+            product(
+                Vendor::Coles,
+                1,
+                "Nudie",
+                "Nudie Nothing But Orange Juice",
+                "1L",
+            ),
+            product(Vendor::Coles, 2, "Berri", "Berri Apple Juice", "2L"),
+            product(Vendor::Coles, 3, "Coles", "Coles Orange Juice", "2L"),
+        ];
+        let woolworths = [
+            product(
+                Vendor::Woolworths,
+                10,
+                "Nudie",
+                "Nudie Nothing But Oranges Juice",
+                "1L",
+            ),
+            product(Vendor::Woolworths, 20, "Berri", "Berri Apple Juice", "1L"),
+            product(
+                Vendor::Woolworths,
+                30,
+                "Woolworths",
+                "Woolworths Orange Juice",
+                "2L",
+            ),
+        ];
+        let coles: Vec<_> = coles.iter().map(|x| Features::new(&tokenizer, x)).collect();
+        let woolworths: Vec<_> = woolworths
+            .iter()
+            .map(|x| Features::new(&tokenizer, x))
+            .collect();
+
+        let pairs: Vec<_> = pair(&coles, &woolworths, 0.5)
+            .into_iter()
+            .map(|x| (x.coles.product.id, x.woolworths.product.id, x.score.total))
+            .collect();
+        // berri differs in size, private labels differ in brand
+        assert_eq!(pairs.len(), 2);
+        assert_eq!((pairs[0].0, pairs[0].1), (1, 10));
+        assert!(pairs[0].2 > 0.99);
+        assert_eq!((pairs[1].0, pairs[1].1), (3, 30));
+        assert!(pairs[1].2 < 0.8);
+    }
+}
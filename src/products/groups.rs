@@ -0,0 +1,299 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt, fs,
+};
+
+use anyhow::{bail, Result};
+use serde::Serialize;
+
+use crate::{
+    prices::{self, RawPriceInfo, RawPrices},
+    Vendor,
+};
+
+use super::{
+    models::ProductId,
+    size::{self, Dimension, Size, Unit},
+    tokens::Tokenizer,
+    UpstreamProduct,
+};
+
+pub fn main(
+    tokenizer: &Tokenizer,
+    products: &[UpstreamProduct],
+    snapshot: &str,
+    category: Option<String>,
+    size: Option<String>,
+) -> Result<()> {
+    let size = match size {
+        Some(x) => match size::parse(&x) {
+            Some(x) => Some(x),
+            None => bail!("Invalid size: {x}"),
+        },
+        None => None,
+    };
+
+    let mut prices = BTreeMap::new();
+    for vendor in Vendor::all() {
+        prices.insert(vendor, prices::load(snapshot, vendor)?);
+    }
+
+    let groups = groups(tokenizer, products);
+    eprintln!("{} products in {} classes", products.len(), groups.len());
+
+    let mut output = Vec::new();
+    for (class, members) in &groups {
+        if category.as_ref().is_some_and(|x| *x != class.category)
+            || size.as_ref().is_some_and(|x| !class.matches(x))
+        {
+            continue;
+        }
+        if let Some(x) = cheapest(class, members, &prices) {
+            println!("{}", x.line());
+            output.push(x);
+        }
+    }
+
+    let mut contents = serde_json::to_string_pretty(&output)?;
+    contents.push('\n');
+    fs::write(format!("data/products/cheapest-{snapshot}.json"), contents)?;
+
+    Ok(())
+}
+
+/// Products that are interchangeable for a shopper: the same classification
+/// and the same size, whatever the brand or retailer.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct Class {
+    /// The most specific token, e.g. `full-cream-milk`
+    pub category: String,
+    pub pack: u32,
+    pub dimension: Dimension,
+    /// The whole package in the base unit of the dimension, e.g. mL
+    pub total: u32,
+}
+
+impl Class {
+    pub fn new(tokenizer: &Tokenizer, product: &UpstreamProduct) -> Option<Self> {
+        let path = tokenizer.classify(&product.brand, &product.name).ok()?;
+        let category = path.last()?.to_string();
+        Some(Self::from_size(category, product.size.as_ref()?))
+    }
+
+    fn from_size(category: String, size: &Size) -> Self {
+        let (dimension, total) = size.comparable();
+        Self {
+            category,
+            pack: size.pack,
+            dimension,
+            total: total.round() as u32,
+        }
+    }
+
+    fn matches(&self, size: &Size) -> bool {
+        let other = Self::from_size(self.category.clone(), size);
+        *self == other
+    }
+
+    fn size(&self) -> Size {
+        let unit = match self.dimension {
+            Dimension::Volume if self.total >= 1000 => Unit::Liters,
+            Dimension::Volume => Unit::Milliliters,
+            Dimension::Mass if self.total >= 1000 => Unit::Kilograms,
+            Dimension::Mass => Unit::Grams,
+            Dimension::Length => Unit::Meters,
+            Dimension::Count => Unit::Each,
+            Dimension::Packs => Unit::Pack,
+            Dimension::Sheets => Unit::Sheets,
+            Dimension::Washes => Unit::Washes,
+        };
+        Size {
+            pack: self.pack,
+            amount_is_total: true,
+            amount: self.total as f32 / unit.multiplier() as f32,
+            unit,
+        }
+    }
+}
+
+impl fmt::Display for Class {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.category, self.size())
+    }
+}
+
+pub fn groups<'a>(
+    tokenizer: &Tokenizer,
+    products: &'a [UpstreamProduct],
+) -> BTreeMap<Class, Vec<&'a UpstreamProduct>> {
+    let mut output: BTreeMap<Class, Vec<&UpstreamProduct>> = BTreeMap::new();
+    for product in products {
+        if let Some(class) = Class::new(tokenizer, product) {
+            output.entry(class).or_default().push(product);
+        }
+    }
+    output
+}
+
+#[derive(Debug, Serialize)]
+pub struct Cheapest {
+    pub class: String,
+    pub price: f32,
+    // often more than one, as prices tie
+    pub offers: Vec<Offer>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Offer {
+    pub product: ProductId,
+    pub name: String,
+    pub stores: Vec<u32>,
+}
+
+impl Cheapest {
+    fn line(&self) -> String {
+        let offers: Vec<String> = self
+            .offers
+            .iter()
+            .map(|x| format!("{} at {} stores", x.name, x.stores.len()))
+            .collect();
+        format!("{}: ${:.2} {}", self.class, self.price, offers.join(", "))
+    }
+}
+
+// including discounts without a membership or multi-buy
+fn price(info: &RawPriceInfo) -> f32 {
+    info.discounts
+        .iter()
+        .filter(|x| x.quantity <= 1 && !x.members_only)
+        .map(|x| f32::from(x.price))
+        .fold(f32::from(info.price), f32::min)
+}
+
+fn cheapest(
+    class: &Class,
+    members: &[&UpstreamProduct],
+    prices: &BTreeMap<Vendor, RawPrices>,
+) -> Option<Cheapest> {
+    let mut best: Option<f32> = None;
+    let mut offers: BTreeMap<ProductId, (String, BTreeSet<u32>)> = BTreeMap::new();
+    for product in members {
+        let Some(groups) = prices.get(&product.vendor).and_then(|x| x.get(&product.id)) else {
+            continue;
+        };
+        for group in groups {
+            let price = price(&group.info);
+            if best.is_some_and(|x| price > x) {
+                continue;
+            }
+            if best.is_none_or(|x| price < x) {
+                best = Some(price);
+                offers.clear();
+            }
+            offers
+                .entry(ProductId::new(product.vendor, product.id))
+                .or_insert_with(|| (product.name.clone(), BTreeSet::new()))
+                .1
+                .extend(&group.stores);
+        }
+    }
+
+    Some(Cheapest {
+        class: class.to_string(),
+        price: best?,
+        offers: offers
+            .into_iter()
+            .map(|(product, (name, stores))| Offer {
+                product,
+                name,
+                stores: stores.into_iter().collect(),
+            })
+            .collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use typed_floats::tf32::NonNaN;
+
+    use super::*;
+    use crate::prices::{Discount, Promotion, RawPriceGroup};
+
+    fn product(vendor: Vendor, id: u32, name: &str, size: &str) -> UpstreamProduct {
+        crate::products::upstream(vendor, id, "", name, size)
+    }
+
+    fn group(stores: &[u32], price: f32, discount: Option<(f32, u32)>) -> RawPriceGroup {
+        RawPriceGroup {
+            stores: stores.to_vec(),
+            info: RawPriceInfo {
+                price: NonNaN::new(price).unwrap(),
+                discounts: discount
+                    .into_iter()
+                    .map(|(price, quantity)| Discount {
+                        price: NonNaN::new(price).unwrap(),
+                        quantity,
+                        members_only: false,
+                    })
+                    .collect(),
+                promotion: Promotion::None,
+            },
+        }
+    }
+
+    #[test]
+    fn cheapest_like_for_like() {
+        let tokenizer = Tokenizer::parse("- Milk\n- Full+Cream +milk\n").unwrap();
+        let products = [
+            product(Vendor::Coles, 1, "Coles Full Cream Milk", "2L"),
+            product(Vendor::Coles, 2, "Pauls Full Cream Milk", "2L"),
+            product(
+                Vendor::Woolworths,
+                3,
+                "Woolworths Full Cream Milk",
+                "2000mL",
+            ),
+            product(Vendor::Woolworths, 4, "Woolworths Full Cream Milk", "3L"),
+            product(Vendor::Coles, 5, "Coles Chocolate", "2L"),
+        ];
+        let groups = groups(&tokenizer, &products);
+        let classes: Vec<String> = groups.keys().map(|x| x.to_string()).collect();
+        assert_eq!(classes, vec!["full-cream-milk 2L", "full-cream-milk 3L"]);
+
+        let prices = BTreeMap::from([
+            (
+                Vendor::Coles,
+                RawPrices::from([
+                    (1, vec![group(&[10, 11], 3.1, None)]),
+                    (2, vec![group(&[10], 4.5, Some((3.1, 1)))]),
+                ]),
+            ),
+            (
+                Vendor::Woolworths,
+                RawPrices::from([(
+                    3,
+                    vec![
+                        group(&[20], 3.3, None),
+                        // multi-buys don't count
+                        group(&[21], 3.5, Some((2.5, 2))),
+                    ],
+                )]),
+            ),
+        ]);
+        let (class, members) = groups.iter().next().unwrap();
+        let cheapest = cheapest(class, members, &prices).unwrap();
+        assert_eq!(cheapest.price, 3.1);
+        assert_eq!(
+            cheapest
+                .offers
+                .iter()
+                .map(|x| (x.product, x.stores.clone()))
+                .collect::<Vec<_>>(),
+            vec![
+                (ProductId::Coles(1), vec![10, 11]),
+                (ProductId::Coles(2), vec![10]),
+            ]
+        );
+        assert!(class.matches(&size::parse("2 L").unwrap()));
+    }
+}
@@ -0,0 +1,134 @@
+use std::{fmt, str::FromStr};
+
+use anyhow::{bail, Error, Result};
+use serde::{Deserialize, Serialize};
+
+/// A barcode normalized to GTIN-14, so EAN-8, UPC-A, EAN-13 and GTIN-14
+/// spellings of the same code compare equal.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Gtin(String);
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Kind {
+    Standard,
+    /// Weighed or priced in store, with the weight or price embedded in the code
+    VariableMeasure,
+    /// Only meaningful within one company or region, e.g. in-store codes
+    RestrictedCirculation,
+}
+
+impl Gtin {
+    pub fn kind(&self) -> Kind {
+        let digits = self.0.as_bytes();
+        match digits {
+            // GTIN-14 indicator digit 9
+            [b'9', ..] => Kind::VariableMeasure,
+            // UPC-A number systems 2 (random weight) and 4 (in-store)
+            [b'0', b'0', b'2', ..] => Kind::VariableMeasure,
+            [b'0', b'0', b'4', ..] => Kind::RestrictedCirculation,
+            // EAN-13 prefixes 20-29
+            [b'0', b'2', ..] => Kind::RestrictedCirculation,
+            // RCN-8, EAN-8 codes starting with 0 or 2
+            [b'0', b'0', b'0', b'0', b'0', b'0', b'0' | b'2', ..] => Kind::RestrictedCirculation,
+            _ => Kind::Standard,
+        }
+    }
+
+    /// Whether the code identifies the same product across retailers.
+    pub fn is_global(&self) -> bool {
+        self.kind() == Kind::Standard
+    }
+}
+
+impl FromStr for Gtin {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        if !s.bytes().all(|x| x.is_ascii_digit()) {
+            bail!("{s} contains non-digits");
+        }
+        if ![8, 12, 13, 14].contains(&s.len()) {
+            bail!("{s} has {} digits", s.len());
+        }
+        let padded = format!("{s:0>14}");
+        if check_digit(&padded[..13]) != padded.as_bytes()[13] - b'0' {
+            bail!("{s} has an invalid check digit");
+        }
+        Ok(Self(padded))
+    }
+}
+
+impl TryFrom<String> for Gtin {
+    type Error = Error;
+
+    fn try_from(value: String) -> Result<Self> {
+        value.parse()
+    }
+}
+
+impl From<Gtin> for String {
+    fn from(value: Gtin) -> Self {
+        value.0
+    }
+}
+
+impl fmt::Display for Gtin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl fmt::Debug for Gtin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Standard GS1 mod 10 check digit, weighting digits 3 and 1 from the right.
+fn check_digit(digits: &str) -> u8 {
+    let sum: u32 = digits
+        .bytes()
+        .rev()
+        .enumerate()
+        .map(|(i, x)| (x - b'0') as u32 * if i % 2 == 0 { 3 } else { 1 })
+        .sum();
+    ((10 - sum % 10) % 10) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gtin(s: &str) -> Gtin {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn normalize() {
+        // the same code as UPC-A, EAN-13 and GTIN-14
+        assert_eq!(gtin("036000291452").to_string(), "00036000291452");
+        assert_eq!(gtin("0036000291452"), gtin("036000291452"));
+        assert_eq!(gtin(" 00036000291452 "), gtin("036000291452"));
+        assert_eq!(gtin("9300633603007").to_string(), "09300633603007");
+        assert_eq!(gtin("96385074").to_string(), "00000096385074");
+
+        assert!("9300633603000".parse::<Gtin>().is_err());
+        assert!("930063360300".parse::<Gtin>().is_err());
+        assert!("93006336O3000".parse::<Gtin>().is_err());
+        assert!("".parse::<Gtin>().is_err());
+    }
+
+    #[test]
+    fn kinds() {
+        assert_eq!(gtin("9300633603007").kind(), Kind::Standard);
+        assert_eq!(gtin("036000291452").kind(), Kind::Standard);
+        assert_eq!(gtin("96385074").kind(), Kind::Standard);
+        assert_eq!(gtin("2123450012343").kind(), Kind::RestrictedCirculation);
+        assert_eq!(gtin("212345001233").kind(), Kind::VariableMeasure);
+        assert_eq!(gtin("412345678903").kind(), Kind::RestrictedCirculation);
+        assert_eq!(gtin("20123451").kind(), Kind::RestrictedCirculation);
+        assert_eq!(gtin("99300633603000").kind(), Kind::VariableMeasure);
+    }
+}
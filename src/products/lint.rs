@@ -0,0 +1,225 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::Path,
+};
+
+use anyhow::{bail, Result};
+use itertools::Itertools;
+
+use super::tokens::{entries, normalize, Entry, RawWord};
+
+pub fn main(path: &Path) -> Result<()> {
+    let problems = lint(&fs::read_to_string(path)?)?;
+    for (line, problem) in &problems {
+        println!("{}:{line}: {problem}", path.display());
+    }
+
+    if !problems.is_empty() {
+        bail!("{} problems in {}", problems.len(), path.display());
+    }
+    eprintln!("No problems in {}", path.display());
+
+    Ok(())
+}
+
+/// Checks the token vocabulary, returning problems sorted by line.
+pub fn lint(yaml: &str) -> Result<Vec<(usize, String)>> {
+    let entries = entries(yaml)?;
+    let mut problems = Vec::new();
+
+    let (groups, entries): (Vec<_>, Vec<_>) = entries.iter().partition(|x| x.group().is_some());
+
+    let mut slugs: BTreeMap<String, &Entry> = BTreeMap::new();
+    for entry in &entries {
+        let slug = entry.slug();
+        if let Some(first) = slugs.get(&slug) {
+            problems.push((
+                entry.line,
+                format!(
+                    "duplicate token {slug} (first defined on line {})",
+                    first.line
+                ),
+            ));
+        } else {
+            slugs.insert(slug, entry);
+        }
+    }
+
+    for entry in &entries {
+        for parent in entry.parents() {
+            if !slugs.contains_key(parent) {
+                let mut problem = format!("unknown parent {parent}");
+                let closest = slugs
+                    .keys()
+                    .map(|x| (strsim::osa_distance(parent, x), x))
+                    .min();
+                if let Some((distance, x)) = closest {
+                    if distance <= 2 {
+                        problem.push_str(&format!(", did you mean {x}?"));
+                    }
+                }
+                problems.push((entry.line, problem));
+            }
+        }
+
+        if !entry.words.iter().any(|x| match x {
+            RawWord::Plain(x) => !normalize(x).trim().is_empty(),
+            RawWord::Parent(_) => true,
+            _ => false,
+        }) {
+            problems.push((
+                entry.line,
+                "token has no words so it can never match".to_string(),
+            ));
+        }
+
+        for word in &entry.words {
+            if let RawWord::Exclude(x) = word {
+                let excluded = normalize(x);
+                let own = entry.words.iter().any(|y| match y {
+                    RawWord::Plain(y) => normalize(y).contains(&excluded),
+                    _ => false,
+                });
+                if own {
+                    problems.push((
+                        entry.line,
+                        format!("excludes {x}, which is part of its own words"),
+                    ));
+                }
+            }
+        }
+    }
+
+    for entry in &groups {
+        if entry.group().unwrap().len() != entry.words.len() {
+            problems.push((
+                entry.line,
+                "exclusive groups can't contain other words".to_string(),
+            ));
+        }
+        for x in entry.group().unwrap() {
+            if !slugs.contains_key(x) {
+                problems.push((entry.line, format!("unknown token {x} in exclusive group")));
+            }
+        }
+    }
+
+    // only report each cycle once, on the first line involved
+    let mut reported = BTreeSet::new();
+    for entry in &entries {
+        if let Some(cycle) = find_cycle(&slugs, &entry.slug()) {
+            let key: BTreeSet<_> = cycle.iter().cloned().collect();
+            if reported.insert(key) {
+                problems.push((entry.line, format!("parent cycle: {}", cycle.join(" > "))));
+            }
+        }
+    }
+
+    // same-length words one swap or substitution apart are usually typos,
+    // e.g. "Bunadberg"
+    let mut words: BTreeMap<String, usize> = BTreeMap::new();
+    for entry in &entries {
+        for word in &entry.words {
+            if let RawWord::Plain(x) = word {
+                for x in x.split(' ') {
+                    words.entry(x.to_string()).or_insert(entry.line);
+                }
+            }
+        }
+    }
+    for ((a, line), (b, other)) in words.iter().tuple_combinations() {
+        if a.len() >= 6
+            && a.len() == b.len()
+            && strsim::osa_distance(&a.to_lowercase(), &b.to_lowercase()) == 1
+        {
+            problems.push((
+                *line.max(other),
+                format!("possible typo: {a} (line {line}) and {b} (line {other})"),
+            ));
+        }
+    }
+
+    problems.sort();
+    Ok(problems)
+}
+
+/// Follows parents from `start`, returning the path if it leads back to itself.
+fn find_cycle(slugs: &BTreeMap<String, &Entry>, start: &str) -> Option<Vec<String>> {
+    fn visit(
+        slugs: &BTreeMap<String, &Entry>,
+        path: &mut Vec<String>,
+        visited: &mut BTreeSet<String>,
+    ) -> bool {
+        let Some(entry) = slugs.get(path.last().unwrap()) else {
+            return false;
+        };
+        for parent in entry.parents() {
+            if parent == path[0] {
+                path.push(parent.to_string());
+                return true;
+            }
+            if visited.insert(parent.to_string()) {
+                path.push(parent.to_string());
+                if visit(slugs, path, visited) {
+                    return true;
+                }
+                path.pop();
+            }
+        }
+        false
+    }
+
+    let mut path = vec![start.to_string()];
+    visit(slugs, &mut path, &mut BTreeSet::new()).then_some(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clean() {
+        let yaml = "- Drink\n- Juice .drink\n- Orange +juice\n";
+        assert_eq!(lint(yaml).unwrap(), Vec::new());
+    }
+
+    #[test]
+    fn problems() {
+        let yaml = r#"- Drink
+- Juice .drink
+- Juice
+- Orange +juise
+- .drink
+- Bundaberg
+- Bunadberg .ginger-beer
+- Ginger+Beer .bunadberg
+- Chocolate -Chocolate
+- =juice =cordial
+"#;
+        assert_eq!(
+            lint(yaml).unwrap(),
+            vec![
+                (
+                    3,
+                    "duplicate token juice (first defined on line 2)".to_string()
+                ),
+                (4, "unknown parent juise, did you mean juice?".to_string()),
+                (5, "token has no words so it can never match".to_string()),
+                (
+                    7,
+                    "parent cycle: bunadberg > ginger-beer > bunadberg".to_string()
+                ),
+                (
+                    7,
+                    "possible typo: Bunadberg (line 7) and Bundaberg (line 6)".to_string()
+                ),
+                (
+                    9,
+                    "excludes Chocolate, which is part of its own words".to_string()
+                ),
+                (10, "unknown token cordial in exclusive group".to_string()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,214 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use anyhow::Result;
+use itertools::Itertools;
+
+use crate::Vendor;
+
+use super::{
+    tokens::{normalize, normalize_phrase, Tokenizer},
+    UpstreamProduct,
+};
+
+const MAX_WORDS: usize = 3;
+const STOPWORDS: &[&str] = &["and", "with", "the", "of", "in", "for", "a", "or", "per"];
+
+pub fn main(
+    tokenizer: &Tokenizer,
+    products: &[UpstreamProduct],
+    ranked: bool,
+    min_count: usize,
+    limit: usize,
+) -> Result<()> {
+    let mut ranks = BTreeMap::new();
+    if ranked {
+        for vendor in Vendor::all() {
+            ranks.insert(vendor, vendor.load_product_ranks()?);
+        }
+    }
+    let weighted: Vec<_> = products
+        .iter()
+        .map(|x| {
+            let weight = match ranks.get(&x.vendor).and_then(|r| r.get(&(x.id as u64))) {
+                Some(rank) => (*rank as f64).ln().max(1.0),
+                None => 1.0,
+            };
+            (x, weight)
+        })
+        .collect();
+
+    let candidates = mine(tokenizer, &weighted, min_count);
+    eprintln!(
+        "Found {} candidates, showing {}",
+        candidates.len(),
+        candidates.len().min(limit)
+    );
+    for x in candidates.iter().take(limit) {
+        println!("{}", x.line());
+    }
+
+    Ok(())
+}
+
+#[derive(Debug)]
+struct Candidate {
+    words: Vec<String>,
+    count: usize,
+    weight: f64,
+    parent: Option<String>,
+}
+
+impl Candidate {
+    /// Formats the candidate in `tokens.yaml` syntax, ready to paste in.
+    fn line(&self) -> String {
+        let mut words = self.words.iter().map(|x| {
+            let mut chars = x.chars();
+            chars
+                .next()
+                .into_iter()
+                .flat_map(char::to_uppercase)
+                .chain(chars)
+                .collect::<String>()
+        });
+        let mut line = format!("- {}", words.join("+"));
+        // hidden so the token doesn't also require the parent's words
+        if let Some(x) = &self.parent {
+            line.push_str(&format!(" .{x}"));
+        }
+        line.push_str(&format!(
+            "  # {} products, weight {:.01}",
+            self.count, self.weight
+        ));
+        line
+    }
+}
+
+fn mine(
+    tokenizer: &Tokenizer,
+    products: &[(&UpstreamProduct, f64)],
+    min_count: usize,
+) -> Vec<Candidate> {
+    let mut counts: BTreeMap<Vec<String>, (usize, f64)> = BTreeMap::new();
+    for (product, weight) in products {
+        if !tokenizer.suggest(&product.brand, &product.name).is_empty() {
+            continue;
+        }
+
+        let brand: BTreeSet<String> = words(&product.brand).into_iter().collect();
+        let words: Vec<String> = words(&product.name)
+            .into_iter()
+            .filter(|x| !brand.contains(x) && !x.contains(|c: char| c.is_ascii_digit()))
+            .collect();
+
+        // count each n-gram once per product
+        let mut seen = BTreeSet::new();
+        for n in 1..=MAX_WORDS {
+            for gram in words.windows(n) {
+                let first = gram.first().unwrap();
+                let last = gram.last().unwrap();
+                if STOPWORDS.contains(&first.as_str()) || STOPWORDS.contains(&last.as_str()) {
+                    continue;
+                }
+                if seen.insert(gram.to_vec()) {
+                    let x = counts.entry(gram.to_vec()).or_default();
+                    x.0 += 1;
+                    x.1 += weight;
+                }
+            }
+        }
+    }
+    counts.retain(|_, (count, _)| *count >= min_count);
+
+    // drop n-grams that mostly appear inside a longer candidate
+    let subsumed: BTreeSet<Vec<String>> = counts
+        .iter()
+        .filter(|(gram, (count, _))| {
+            counts.iter().any(|(other, (other_count, _))| {
+                other.len() > gram.len()
+                    && other.windows(gram.len()).any(|x| x == gram.as_slice())
+                    && *other_count * 5 >= *count * 4
+            })
+        })
+        .map(|(gram, _)| gram.clone())
+        .collect();
+
+    let mut candidates: Vec<Candidate> = counts
+        .into_iter()
+        .filter(|(gram, _)| !subsumed.contains(gram))
+        .map(|(words, (count, weight))| {
+            let parent = propose_parent(tokenizer, products, &words);
+            Candidate {
+                words,
+                count,
+                weight,
+                parent,
+            }
+        })
+        .collect();
+    candidates.sort_by(|a, b| b.weight.total_cmp(&a.weight).then(a.words.cmp(&b.words)));
+    candidates
+}
+
+// the token most often seen with the n-gram, if in at least a third of names
+fn propose_parent(
+    tokenizer: &Tokenizer,
+    products: &[(&UpstreamProduct, f64)],
+    gram: &[String],
+) -> Option<String> {
+    let needle = format!(" {} ", gram.join(" "));
+    let mut total = 0;
+    let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+    for (product, _) in products {
+        if normalize(&product.name).contains(&needle) {
+            total += 1;
+            for x in tokenizer.suggest(&product.brand, &product.name) {
+                *counts.entry(x).or_default() += 1;
+            }
+        }
+    }
+
+    let (slug, count) = counts.into_iter().max_by_key(|(_, x)| *x)?;
+    (count * 3 >= total).then(|| slug.to_string())
+}
+
+fn words(text: &str) -> Vec<String> {
+    normalize_phrase(text)
+        .split_whitespace()
+        .map(String::from)
+        .collect_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn product(brand: &str, name: &str) -> UpstreamProduct {
+        crate::products::upstream(Vendor::Coles, 0, brand, name, "")
+    }
+
+    #[test]
+    fn candidates() {
+        let tokenizer = Tokenizer::parse("- Drink\n- Water .drink\n").unwrap();
+        let products = [
+            product(
+                "Mount Franklin",
+                "Mount Franklin Sparkling Water Lime 1.25L",
+            ),
+            product("Coles", "Coles Sparkling Mineral Water 1.25L"),
+            product("Nu Pure", "Nu Pure Sparkling Spring Water 6 x 1L"),
+            product("Kirks", "Kirks Sparkling Lime 1.25L"),
+            product("Capi", "Capi Sparkling Lime & Mint 250mL"),
+            product("Coles", "Coles Sparkling Lime Water 1.25L"),
+            product("Coles", "Coles Kettle Chips Sea Salt 175g"),
+        ];
+        let weighted: Vec<_> = products.iter().map(|x| (x, 1.0)).collect();
+        let lines: Vec<_> = mine(&tokenizer, &weighted, 2)
+            .iter()
+            .map(|x| x.line())
+            .collect();
+        assert_eq!(
+            lines,
+            vec!["- Sparkling+Lime .water  # 2 products, weight 2.0"]
+        );
+    }
+}
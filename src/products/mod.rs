@@ -5,65 +5,215 @@ use std::{
 };
 
 use anyhow::Result;
+use clap::Subcommand;
 use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
 
 use crate::Vendor;
 
-use self::{size::Size, tokens::Tokenizer};
+use self::{nutrition::Nutrition, size::Size, tokens::Tokenizer};
 
+mod barcodes;
+mod catalogue;
 mod coles;
+mod fuzzy;
+mod groups;
+mod gtin;
+mod lint;
+mod mine;
+mod models;
+mod nutrition;
 mod size;
+mod taxonomy;
 mod tokens;
 mod woolworths;
 
-pub fn main() -> Result<()> {
-    let path = Path::new("data/products/raw.jsonl");
-    let mut raw: Vec<UpstreamProduct> = Vec::new();
-    if path.exists() {
-        for result in BufReader::new(File::open(path)?).lines() {
-            raw.push(serde_json::from_str(&result?)?);
+const TOKENS_PATH: &str = "data/products/tokens.yaml";
+
+/// Written as the first line of the upstream cache, and bumped whenever sizes
+/// or nutrition are parsed differently so older caches are rebuilt.
+const UPSTREAM_VERSION: u32 = 1;
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    Match,
+    /// Match products without a shared barcode by brand, classification,
+    /// size and name
+    Fuzzy {
+        /// Minimum score to accept a match
+        #[arg(long, default_value_t = 0.8)]
+        threshold: f64,
+        /// Minimum score to queue a match for review
+        #[arg(long, default_value_t = 0.6)]
+        review: f64,
+    },
+    Tokens {
+        #[command(subcommand)]
+        command: TokensCommand,
+    },
+    Catalogue {
+        #[command(subcommand)]
+        command: CatalogueCommand,
+    },
+    /// Export parsed nutrition panels
+    Nutrition,
+    /// Find the cheapest product of each classification and size
+    Cheapest {
+        /// Price snapshot name, as in data/prices/{snapshot}-{vendor}.bin.zst
+        snapshot: String,
+        #[arg(long)]
+        category: Option<String>,
+        #[arg(long)]
+        size: Option<String>,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum CatalogueCommand {
+    Check,
+    /// Rewrite the catalogue in canonical order, filling in ids and categories
+    Format,
+    /// Add products for barcode and fuzzy matches not yet in the catalogue
+    Update,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum TokensCommand {
+    Lint,
+    Export,
+    Mine {
+        /// Weight products by their rank
+        #[arg(long)]
+        ranked: bool,
+        #[arg(long, default_value_t = 5)]
+        min_count: usize,
+        #[arg(long, default_value_t = 50)]
+        limit: usize,
+    },
+}
+
+pub fn main(command: Option<Command>) -> Result<()> {
+    match command {
+        None => classify(),
+        Some(Command::Match) => barcodes::main(),
+        Some(Command::Fuzzy { threshold, review }) => {
+            let tokenizer = Tokenizer::load(Path::new(TOKENS_PATH))?;
+            fuzzy::main(&tokenizer, &load_upstream()?, threshold, review)
         }
-    } else {
-        for vendor in Vendor::all() {
-            let ranks = vendor.load_product_ranks()?;
-            let products = match vendor {
-                Vendor::Coles => coles::load()?,
-                Vendor::Woolworths => woolworths::load()?,
-            };
-            let total = products.len();
-            let mut skipped = 0usize;
-            for product in products {
-                if let Some(rank) = ranks.get(&(product.id as u64)) {
-                    if *rank > 1000 {
-                        raw.push(product);
-                    } else {
-                        skipped += 1;
-                    }
-                }
+        Some(Command::Catalogue { command }) => {
+            let tokenizer = Tokenizer::load(Path::new(TOKENS_PATH))?;
+            match command {
+                CatalogueCommand::Check => catalogue::check(&tokenizer),
+                CatalogueCommand::Format => catalogue::format(&tokenizer),
+                CatalogueCommand::Update => catalogue::update(&tokenizer, &load_upstream()?),
             }
-            eprintln!(
-                "Loaded {} {vendor} products (skipped {skipped})",
-                total - skipped
-            )
         }
-
-        let mut file = File::create(path)?;
-        for x in &raw {
-            writeln!(file, "{}", serde_json::to_string(x)?)?;
+        Some(Command::Cheapest {
+            snapshot,
+            category,
+            size,
+        }) => {
+            let tokenizer = Tokenizer::load(Path::new(TOKENS_PATH))?;
+            groups::main(&tokenizer, &load_upstream()?, &snapshot, category, size)
         }
-    };
+        Some(Command::Nutrition) => nutrition::main(&load_upstream()?),
+        Some(Command::Tokens { command }) => match command {
+            TokensCommand::Lint => lint::main(Path::new(TOKENS_PATH)),
+            TokensCommand::Export => {
+                let tokenizer = Tokenizer::load(Path::new(TOKENS_PATH))?;
+                taxonomy::main(&tokenizer, &load_upstream()?)
+            }
+            TokensCommand::Mine {
+                ranked,
+                min_count,
+                limit,
+            } => {
+                let tokenizer = Tokenizer::load(Path::new(TOKENS_PATH))?;
+                mine::main(&tokenizer, &load_upstream()?, ranked, min_count, limit)
+            }
+        },
+    }
+}
 
-    let tokenizer = Tokenizer::load(Path::new("data/products/tokens.yaml"))?;
+fn classify() -> Result<()> {
+    let raw = load_upstream()?;
+    let tokenizer = Tokenizer::load(Path::new(TOKENS_PATH))?;
+    let mut conflicts = 0usize;
     for product in raw {
-        let suggestions = tokenizer.suggest(&product.name);
-        if suggestions.len() > 1 {
-            println!("{} {suggestions:?}", product.name)
+        match tokenizer.classify(&product.brand, &product.name) {
+            Ok(path) if path.is_empty() => (),
+            Ok(path) => println!("{} {}", product.name, path.join(" > ")),
+            Err(e) => {
+                conflicts += 1;
+                println!("{e}")
+            }
         }
     }
+    eprintln!("{conflicts} products matched unrelated tokens");
 
     Ok(())
 }
 
+fn load_upstream() -> Result<Vec<UpstreamProduct>> {
+    let path = Path::new("data/products/raw.jsonl");
+    if let Some(raw) = cached_upstream(path)? {
+        return Ok(raw);
+    }
+
+    let mut raw: Vec<UpstreamProduct> = Vec::new();
+    for vendor in Vendor::all() {
+        let ranks = vendor.load_product_ranks()?;
+        let products = match vendor {
+            Vendor::Coles => coles::load()?,
+            Vendor::Woolworths => woolworths::load()?,
+        };
+        let total = products.len();
+        let mut skipped = 0usize;
+        for product in products {
+            if let Some(rank) = ranks.get(&(product.id as u64)) {
+                if *rank > 1000 {
+                    raw.push(product);
+                } else {
+                    skipped += 1;
+                }
+            }
+        }
+        eprintln!(
+            "Loaded {} {vendor} products (skipped {skipped})",
+            total - skipped
+        )
+    }
+
+    let mut file = File::create(path)?;
+    writeln!(file, "{}", json!({ "version": UPSTREAM_VERSION }))?;
+    for x in &raw {
+        writeln!(file, "{}", serde_json::to_string(x)?)?;
+    }
+
+    Ok(raw)
+}
+
+fn cached_upstream(path: &Path) -> Result<Option<Vec<UpstreamProduct>>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let mut lines = BufReader::new(File::open(path)?).lines();
+    let version = match lines.next() {
+        Some(x) => serde_json::from_str::<Value>(&x?)?["version"].as_u64(),
+        None => None,
+    };
+    if version != Some(UPSTREAM_VERSION.into()) {
+        eprintln!("Rebuilding {}, which is out of date", path.display());
+        return Ok(None);
+    }
+
+    let mut raw = Vec::new();
+    for result in lines {
+        raw.push(serde_json::from_str(&result?)?);
+    }
+    Ok(Some(raw))
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 struct UpstreamProduct {
     vendor: Vendor,
@@ -71,5 +221,20 @@ struct UpstreamProduct {
     brand: String,
     name: String,
     description: String,
-    size: Option<String>,
+    size: Option<Size>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    nutrition: Option<Nutrition>,
+}
+
+#[cfg(test)]
+fn upstream(vendor: Vendor, id: u32, brand: &str, name: &str, size: &str) -> UpstreamProduct {
+    UpstreamProduct {
+        vendor,
+        id,
+        brand: brand.to_string(),
+        name: name.to_string(),
+        description: String::new(),
+        size: size::parse(size),
+        nutrition: None,
+    }
 }
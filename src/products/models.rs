@@ -0,0 +1,63 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::Vendor;
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", tag = "vendor", content = "id")]
+pub enum ProductId {
+    Coles(u32),
+    Woolworths(u32),
+}
+
+impl ProductId {
+    pub fn new(vendor: Vendor, id: u32) -> Self {
+        match vendor {
+            Vendor::Coles => Self::Coles(id),
+            Vendor::Woolworths => Self::Woolworths(id),
+        }
+    }
+
+    pub fn vendor(&self) -> Vendor {
+        match self {
+            Self::Coles(_) => Vendor::Coles,
+            Self::Woolworths(_) => Vendor::Woolworths,
+        }
+    }
+
+    pub fn coles(&self) -> Option<u32> {
+        match self {
+            Self::Coles(x) => Some(*x),
+            _ => None,
+        }
+    }
+
+    pub fn woolworths(&self) -> Option<u32> {
+        match self {
+            Self::Woolworths(x) => Some(*x),
+            _ => None,
+        }
+    }
+}
+
+impl Vendor {
+    pub fn parse_product_id(&self, id: &str) -> Option<ProductId> {
+        Some(ProductId::new(*self, id.parse().ok()?))
+    }
+}
+
+impl fmt::Debug for ProductId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Coles(x) => write!(
+                f,
+                "[Coles Product {x}](https://www.coles.com.au/product/-{x})"
+            ),
+            Self::Woolworths(x) => write!(
+                f,
+                "[Woolworths Product {x}](https://www.woolworths.com.au/shop/productdetails/{x})"
+            ),
+        }
+    }
+}
@@ -0,0 +1,223 @@
+use std::{fs, ops::Mul};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    models::ProductId,
+    size::{self, Dimension, Size},
+    UpstreamProduct,
+};
+
+const OUTPUT_PATH: &str = "data/products/nutrition.jsonl";
+
+/// Writes the nutrition panel of every product that has one.
+pub fn main(products: &[UpstreamProduct]) -> Result<()> {
+    let mut contents = String::new();
+    let mut count = 0usize;
+    for product in products {
+        let Some(nutrition) = &product.nutrition else {
+            continue;
+        };
+        contents.push_str(&serde_json::to_string(&Export {
+            product: ProductId::new(product.vendor, product.id),
+            name: &product.name,
+            size: product.size.as_ref(),
+            nutrition,
+            total: product.size.as_ref().and_then(|x| nutrition.total(x)),
+        })?);
+        contents.push('\n');
+        count += 1;
+    }
+    fs::write(OUTPUT_PATH, contents)?;
+    eprintln!(
+        "Exported nutrition for {count} of {} products",
+        products.len()
+    );
+
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct Export<'a> {
+    product: ProductId,
+    name: &'a str,
+    size: Option<&'a Size>,
+    nutrition: &'a Nutrition,
+    /// The whole package, for price per gram of protein and the like
+    total: Option<Nutrients>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Nutrition {
+    pub serving_size: Option<Size>,
+    pub per_100: Nutrients,
+    pub per_serving: Nutrients,
+}
+
+/// Amounts from a nutrition panel. Anything missing from the panel is `None`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Nutrients {
+    pub energy_kj: Option<f32>,
+    pub protein_g: Option<f32>,
+    pub fat_g: Option<f32>,
+    pub sugars_g: Option<f32>,
+    pub sodium_mg: Option<f32>,
+}
+
+impl Mul<f32> for Nutrients {
+    type Output = Self;
+
+    fn mul(self, x: f32) -> Self {
+        Self {
+            energy_kj: self.energy_kj.map(|y| y * x),
+            protein_g: self.protein_g.map(|y| y * x),
+            fat_g: self.fat_g.map(|y| y * x),
+            sugars_g: self.sugars_g.map(|y| y * x),
+            sodium_mg: self.sodium_mg.map(|y| y * x),
+        }
+    }
+}
+
+impl Nutrition {
+    /// Parses a Coles nutrition panel, returning `None` if it has no
+    /// nutrients at all.
+    pub fn parse(serving_size: &str, breakdown: &[RawBreakdown]) -> Option<Self> {
+        let mut per_100 = Nutrients::default();
+        let mut per_serving = Nutrients::default();
+        for nutrient in breakdown.iter().flat_map(|x| &x.nutrients) {
+            per_100.set(&nutrient.nutrient, &nutrient.unit_size);
+            per_serving.set(&nutrient.nutrient, &nutrient.serving_size);
+        }
+        if per_100 == Nutrients::default() && per_serving == Nutrients::default() {
+            return None;
+        }
+
+        Some(Self {
+            serving_size: size::parse(serving_size),
+            per_100,
+            per_serving,
+        })
+    }
+
+    /// Scales the per 100g or 100mL amounts to the whole package.
+    pub fn total(&self, size: &Size) -> Option<Nutrients> {
+        match size.comparable() {
+            (Dimension::Mass | Dimension::Volume, x) => Some(self.per_100 * (x / 100.0)),
+            _ => None,
+        }
+    }
+}
+
+impl Nutrients {
+    fn set(&mut self, name: &str, value: &Option<String>) {
+        let Some((amount, unit)) = value.as_deref().and_then(amount) else {
+            return;
+        };
+        let name: String = name
+            .chars()
+            .filter(|x| x.is_alphabetic())
+            .flat_map(char::to_lowercase)
+            .collect();
+        // saturated fat, sugar alcohols and the like are deliberately ignored
+        match (name.as_str(), unit.as_str()) {
+            ("energy", "kj") => self.energy_kj = Some(amount),
+            ("energy", "cal" | "kcal") => self.energy_kj = Some(amount * 4.184),
+            ("protein", _) => self.protein_g = grams(amount, &unit),
+            ("fattotal" | "totalfat" | "fat", _) => self.fat_g = grams(amount, &unit),
+            ("sugars" | "sugar", _) => self.sugars_g = grams(amount, &unit),
+            ("sodium", _) => self.sodium_mg = grams(amount, &unit).map(|x| x * 1000.0),
+            _ => (),
+        }
+    }
+}
+
+fn grams(amount: f32, unit: &str) -> Option<f32> {
+    match unit {
+        "g" => Some(amount),
+        "mg" => Some(amount / 1000.0),
+        "ug" | "µg" | "mcg" => Some(amount / 1_000_000.0),
+        _ => None,
+    }
+}
+
+/// Splits a panel value such as "436kJ", "3.4 g" or "<1g" into its amount
+/// and lowercase unit. Values below a limit are taken as the limit.
+fn amount(s: &str) -> Option<(f32, String)> {
+    let s = s.trim().trim_start_matches(['<', '>', '~']).trim();
+    let (amount, unit) = size::split_unit(s)?;
+    Some((amount, unit.to_lowercase()))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RawBreakdown {
+    #[serde(default)]
+    nutrients: Vec<RawNutrient>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawNutrient {
+    nutrient: String,
+    /// Per serving
+    serving_size: Option<String>,
+    /// Per 100g or 100mL
+    unit_size: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn coles_panel() {
+        let breakdown: Vec<RawBreakdown> = serde_json::from_str(
+            r#"[{"title": "Nutrition", "nutrients": [
+                {"nutrient": "Energy", "servingSize": "688kJ", "unitSize": "275kJ"},
+                {"nutrient": "Protein", "servingSize": "8.5g", "unitSize": "3.4g"},
+                {"nutrient": "Fat, total", "servingSize": "9.0g", "unitSize": "3.6g"},
+                {"nutrient": "- Saturated", "servingSize": "5.8g", "unitSize": "2.3g"},
+                {"nutrient": "Carbohydrate", "servingSize": "12.0g", "unitSize": "4.8g"},
+                {"nutrient": "- Sugars", "servingSize": "12.0g", "unitSize": "4.8g"},
+                {"nutrient": "Sodium", "servingSize": "110mg", "unitSize": "44mg"},
+                {"nutrient": "Calcium", "servingSize": "300mg", "unitSize": "120mg"}
+            ]}]"#,
+        )
+        .unwrap();
+        let nutrition = Nutrition::parse("250mL", &breakdown).unwrap();
+        assert_eq!(
+            nutrition.per_100,
+            Nutrients {
+                energy_kj: Some(275.0),
+                protein_g: Some(3.4),
+                fat_g: Some(3.6),
+                sugars_g: Some(4.8),
+                sodium_mg: Some(44.0),
+            }
+        );
+        assert_eq!(nutrition.per_serving.sodium_mg, Some(110.0));
+        assert_eq!(nutrition.serving_size, size::parse("250mL"));
+
+        let total = nutrition.total(&size::parse("2L").unwrap()).unwrap();
+        assert_eq!(total.protein_g, Some(68.0));
+        assert_eq!(nutrition.total(&size::parse("6 pack").unwrap()), None);
+
+        assert_eq!(Nutrition::parse("250mL", &[]), None);
+    }
+
+    #[test]
+    fn values() {
+        assert_eq!(amount("<1g"), Some((1.0, "g".to_string())));
+        assert_eq!(amount("0.05 g"), Some((0.05, "g".to_string())));
+        assert_eq!(amount("N/A"), None);
+
+        let mut x = Nutrients::default();
+        x.set("Energy", &Some("100Cal".to_string()));
+        x.set("Sodium", &Some("0.4g".to_string()));
+        x.set("Sugars", &Some("12kJ".to_string()));
+        assert_eq!(x.energy_kj, Some(418.4));
+        assert_eq!(x.sodium_mg, Some(400.0));
+        assert_eq!(x.sugars_g, None);
+    }
+}
@@ -1,8 +1,16 @@
-use std::{fmt, str::FromStr};
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Write},
+    fs,
+    str::FromStr,
+};
 
 use anyhow::Result;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone)]
+use crate::Vendor;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Size {
     pub pack: u32,
     pub amount_is_total: bool,
@@ -28,26 +36,81 @@ impl Size {
         }
     }
 
-    pub fn comparable(&self) -> f32 {
-        self.total() * (self.unit.multiplier() as f32)
+    /// The total in the base unit of its dimension, which is only
+    /// comparable with other sizes of the same dimension.
+    pub fn comparable(&self) -> (Dimension, f32) {
+        (
+            self.unit.dimension(),
+            self.total() * (self.unit.multiplier() as f32),
+        )
+    }
+}
+
+/// Formats the size so [`parse`] reads it back, e.g. "1.25L", "6 x 375mL",
+/// "12 Pack 700g" or "24 Pack".
+impl fmt::Display for Size {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.pack, self.amount_is_total) {
+            (1, _) => (),
+            (n, _) if self.unit == Unit::Pack && self.total() == n as f32 => {
+                return write!(f, "{n} Pack");
+            }
+            (n, false) => write!(f, "{n} x ")?,
+            (n, true) => write!(f, "{n} Pack ")?,
+        }
+        match self.unit.dimension() {
+            Dimension::Volume | Dimension::Mass | Dimension::Length => {
+                write!(f, "{}{}", self.amount, self.unit)
+            }
+            _ => write!(f, "{} {}", self.amount, self.unit),
+        }
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Dimension {
+    Volume,
+    Mass,
+    Length,
+    Count,
+    Packs,
+    Sheets,
+    Washes,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
 pub enum Unit {
     Liters,
     Milliliters,
     Kilograms,
     Grams,
+    Meters,
+    Each,
+    Pack,
+    Sheets,
+    Washes,
 }
 
 impl Unit {
+    pub fn dimension(&self) -> Dimension {
+        match self {
+            Self::Liters | Self::Milliliters => Dimension::Volume,
+            Self::Kilograms | Self::Grams => Dimension::Mass,
+            Self::Meters => Dimension::Length,
+            Self::Each => Dimension::Count,
+            Self::Pack => Dimension::Packs,
+            Self::Sheets => Dimension::Sheets,
+            Self::Washes => Dimension::Washes,
+        }
+    }
+
     pub fn multiplier(&self) -> u32 {
         match self {
             Self::Liters => 1000,
-            Self::Milliliters => 1,
             Self::Kilograms => 1000,
-            Self::Grams => 1,
+            _ => 1,
         }
     }
 }
@@ -57,10 +120,15 @@ impl FromStr for Unit {
 
     fn from_str(s: &str) -> Result<Self, ()> {
         Ok(match s {
-            "l" => Self::Liters,
+            "l" | "lt" | "litre" | "litres" => Self::Liters,
             "ml" => Self::Milliliters,
             "kg" => Self::Kilograms,
-            "g" => Self::Grams,
+            "g" | "gm" => Self::Grams,
+            "m" | "metre" | "metres" => Self::Meters,
+            "ea" | "each" => Self::Each,
+            "pk" | "pack" | "packs" => Self::Pack,
+            "sheet" | "sheets" => Self::Sheets,
+            "wash" | "washes" => Self::Washes,
             _ => return Err(()),
         })
     }
@@ -73,16 +141,147 @@ impl fmt::Display for Unit {
             Self::Milliliters => write!(f, "mL"),
             Self::Kilograms => write!(f, "kg"),
             Self::Grams => write!(f, "g"),
+            Self::Meters => write!(f, "m"),
+            Self::Each => write!(f, "ea"),
+            Self::Pack => write!(f, "pk"),
+            Self::Sheets => write!(f, "sheets"),
+            Self::Washes => write!(f, "washes"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Lexeme {
+    Number(f32),
+    Unit(Unit),
+    Times,
+    Dash,
+    Of,
+    Per,
+}
+
+fn lex(s: &str) -> Vec<Lexeme> {
+    let mut output = Vec::new();
+    let mut chars = s.char_indices().peekable();
+    while let Some((i, x)) = chars.next() {
+        // a leading decimal point too, e.g. ".5L"
+        let decimal = x == '.' && s[i + 1..].starts_with(|c: char| c.is_ascii_digit());
+        if x.is_ascii_digit() || decimal {
+            let mut end = i + 1;
+            while let Some((j, y)) = chars.peek().copied() {
+                let decimal = y == '.' && s[j + 1..].starts_with(|c: char| c.is_ascii_digit());
+                if !(y.is_ascii_digit() || decimal) {
+                    break;
+                }
+                end = j + 1;
+                chars.next();
+            }
+            if let Ok(x) = s[i..end].parse() {
+                output.push(Lexeme::Number(x));
+            }
+        } else if x.is_alphabetic() {
+            let mut end = i + x.len_utf8();
+            while let Some((j, y)) = chars.peek().copied() {
+                if !y.is_alphabetic() {
+                    break;
+                }
+                end = j + y.len_utf8();
+                chars.next();
+            }
+            let word = s[i..end].to_lowercase();
+            match word.as_str() {
+                "x" => output.push(Lexeme::Times),
+                "of" => output.push(Lexeme::Of),
+                "per" => output.push(Lexeme::Per),
+                word => {
+                    if let Ok(unit) = Unit::from_str(word) {
+                        output.push(Lexeme::Unit(unit));
+                    }
+                    // anything else ("approx", "cans", ...) is noise
+                }
+            }
+        } else {
+            match x {
+                '×' | '*' => output.push(Lexeme::Times),
+                '-' | '–' => output.push(Lexeme::Dash),
+                _ => (),
+            }
         }
     }
+    output
+}
+
+/// Parses a retailer size string such as "2L", "6 x 375mL", "24 Pack",
+/// "500g-600g" or "approx. 1kg".
+pub fn parse(s: &str) -> Option<Size> {
+    use Lexeme::*;
+
+    let per_item = |pack: f32, amount, unit| {
+        Some(Size {
+            pack: count(pack)?,
+            amount_is_total: false,
+            amount,
+            unit,
+        })
+    };
+    let total = |pack: f32, amount, unit| {
+        Some(Size {
+            pack: count(pack)?,
+            amount_is_total: true,
+            amount,
+            unit,
+        })
+    };
+
+    match lex(s).as_slice() {
+        [Unit(unit)] if unit.dimension() == Dimension::Count => Some(Size::amount(1.0, *unit)),
+        // "24 Pack" or "pack of 6", where the count is known but not what's in it
+        [Number(n), Unit(self::Unit::Pack)] | [Unit(self::Unit::Pack), Of, Number(n)] => {
+            per_item(*n, 1.0, self::Unit::Pack)
+        }
+        [Number(a), Unit(u)] => Some(Size::amount(*a, *u)),
+        // ranges use the midpoint, e.g. "500g-600g" or "500-600g"
+        [Number(a), Dash, Number(b), Unit(u)] => Some(Size::amount((a + b) / 2.0, *u)),
+        [Number(a), Unit(u), Dash, Number(b), Unit(v)] if u == v => {
+            Some(Size::amount((a + b) / 2.0, *u))
+        }
+        // "6 x 375mL" or "375mL x 6"
+        [Number(n), Times, Number(a), Unit(u)] | [Number(a), Unit(u), Times, Number(n)] => {
+            per_item(*n, *a, *u)
+        }
+        // "10 Pack 185g" or "185g 10 Pack", where the amount is the whole pack
+        [Number(n), Unit(self::Unit::Pack), Number(a), Unit(u)]
+        | [Number(a), Unit(u), Number(n), Unit(self::Unit::Pack)] => total(*n, *a, *u),
+        _ => None,
+    }
+}
+
+fn count(x: f32) -> Option<u32> {
+    (x >= 1.0 && x.fract() == 0.0).then_some(x as u32)
+}
+
+/// Builds a total size from a nutrition panel, e.g. 8 servings of "250mL".
+pub fn from_servings(serving_size: &str, servings_per_package: &str) -> Option<Size> {
+    let servings: f32 = match lex(servings_per_package).as_slice() {
+        [Lexeme::Number(x)] => *x,
+        _ => return None,
+    };
+    let (amount, unit) = find_amount(serving_size)?;
+    Some(Size::amount(servings * amount, unit))
 }
 
 pub fn find_amount(s: &str) -> Option<(f32, Unit)> {
-    for word in s.split(' ') {
+    let words: Vec<_> = s.split(' ').filter(|x| !x.is_empty()).collect();
+    for (i, word) in words.iter().enumerate() {
         if let Some((a, b)) = split_unit(word) {
             if let Ok(b) = Unit::from_str(&b.to_lowercase()) {
                 return Some((a, b));
             }
+        } else if let (Ok(a), Some(b)) = (word.parse(), words.get(i + 1)) {
+            // number and unit separated by a space, e.g. "700 g"
+            if let Ok(b) = Unit::from_str(&b.to_lowercase()) {
+                return Some((a, b));
+            }
         }
     }
 
@@ -90,14 +289,14 @@ pub fn find_amount(s: &str) -> Option<(f32, Unit)> {
 }
 
 pub fn split_unit(s: &str) -> Option<(f32, &str)> {
-    for (i, x) in s.chars().enumerate() {
+    for (i, x) in s.char_indices() {
         if i == 0 && !x.is_ascii_digit() {
             return None;
         }
 
         if x.is_alphabetic() {
             let (a, b) = s.split_at(i);
-            if let Some(a) = a.trim().parse().ok() {
+            if let Ok(a) = a.trim().parse() {
                 return Some((a, b.trim()));
             }
         }
@@ -105,3 +304,145 @@ pub fn split_unit(s: &str) -> Option<(f32, &str)> {
 
     None
 }
+
+/// Tracks how many size strings were parsed so the gaps can be reviewed.
+#[derive(Default)]
+pub struct Coverage {
+    parsed: usize,
+    unparsed: BTreeMap<String, usize>,
+}
+
+impl Coverage {
+    pub fn record(&mut self, raw: &str, size: &Option<Size>) {
+        if size.is_some() {
+            self.parsed += 1;
+        } else {
+            *self.unparsed.entry(raw.to_string()).or_default() += 1;
+        }
+    }
+
+    pub fn write(&self, vendor: Vendor) -> Result<()> {
+        let unparsed: usize = self.unparsed.values().sum();
+        let total = self.parsed + unparsed;
+        let percent = self.parsed as f64 / total.max(1) as f64 * 100.0;
+        eprintln!("Parsed {percent:.01}% of {vendor} sizes ({unparsed} unparsed)");
+
+        let mut md = String::new();
+        writeln!(md, "## Statistics\n")?;
+        writeln!(md, "- {total} products")?;
+        writeln!(md, "- {} parsed", self.parsed)?;
+        writeln!(md, "- {percent:.01}% coverage")?;
+        writeln!(md)?;
+        if !self.unparsed.is_empty() {
+            let mut unparsed: Vec<_> = self.unparsed.iter().collect();
+            unparsed.sort_by(|(a, x), (b, y)| y.cmp(x).then(a.cmp(b)));
+
+            writeln!(md, "## Unparsed\n")?;
+            for (raw, count) in unparsed {
+                writeln!(md, "- `{raw}` ({count})")?;
+            }
+        }
+        fs::write(format!("data/products/sizes-{}.md", vendor.slug()), md)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_sizes() {
+        for (raw, expected) in [
+            // woolworths package_size
+            ("2L", Some(Size::amount(2.0, Unit::Liters))),
+            ("375mL", Some(Size::amount(375.0, Unit::Milliliters))),
+            ("1.25L", Some(Size::amount(1.25, Unit::Liters))),
+            (".5L", Some(Size::amount(0.5, Unit::Liters))),
+            ("0.5L", Some(Size::amount(0.5, Unit::Liters))),
+            ("500g", Some(Size::amount(500.0, Unit::Grams))),
+            ("1kg", Some(Size::amount(1.0, Unit::Kilograms))),
+            ("Each", Some(Size::amount(1.0, Unit::Each))),
+            ("180 sheets", Some(Size::amount(180.0, Unit::Sheets))),
+            ("60 pack", Some(pack(60, false, 1.0, Unit::Pack))),
+            ("30m", Some(Size::amount(30.0, Unit::Meters))),
+            ("40 washes", Some(Size::amount(40.0, Unit::Washes))),
+            // coles size
+            ("2l", Some(Size::amount(2.0, Unit::Liters))),
+            ("150ml", Some(Size::amount(150.0, Unit::Milliliters))),
+            ("1.5kg", Some(Size::amount(1.5, Unit::Kilograms))),
+            ("10 Pack", Some(pack(10, false, 1.0, Unit::Pack))),
+            ("1 each", Some(Size::amount(1.0, Unit::Each))),
+            ("12pk", Some(pack(12, false, 1.0, Unit::Pack))),
+            ("700 g", Some(Size::amount(700.0, Unit::Grams))),
+            ("per kg", None),
+            // multipacks
+            ("6 x 375mL", Some(pack(6, false, 375.0, Unit::Milliliters))),
+            ("24X375ML", Some(pack(24, false, 375.0, Unit::Milliliters))),
+            ("4 x 1.25L", Some(pack(4, false, 1.25, Unit::Liters))),
+            (
+                "375ml x 30",
+                Some(pack(30, false, 375.0, Unit::Milliliters)),
+            ),
+            ("10 Pack 185g", Some(pack(10, true, 185.0, Unit::Grams))),
+            ("700g 12 pack", Some(pack(12, true, 700.0, Unit::Grams))),
+            ("24 pack", Some(pack(24, false, 1.0, Unit::Pack))),
+            ("pack of 6", Some(pack(6, false, 1.0, Unit::Pack))),
+            // ranges and approximations
+            ("500g-600g", Some(Size::amount(550.0, Unit::Grams))),
+            ("1-1.5kg", Some(Size::amount(1.25, Unit::Kilograms))),
+            ("approx. 1kg", Some(Size::amount(1.0, Unit::Kilograms))),
+            ("Approx 180g", Some(Size::amount(180.0, Unit::Grams))),
+            ("0.5 x 6", None),
+            ("1.5 pack", None),
+        ] {
+            assert_eq!(parse(raw), expected, "{raw}");
+            if let Some(x) = expected {
+                assert_eq!(parse(&x.to_string()), Some(x.clone()), "{x}");
+            }
+        }
+    }
+
+    #[test]
+    fn comparable_within_dimension() {
+        assert_eq!(
+            Size::amount(2.0, Unit::Liters).comparable(),
+            (Dimension::Volume, 2000.0)
+        );
+        assert_eq!(
+            Size::amount(500.0, Unit::Grams).comparable(),
+            (Dimension::Mass, 500.0)
+        );
+        assert_ne!(
+            Size::amount(24.0, Unit::Sheets).comparable().0,
+            Size::amount(24.0, Unit::Washes).comparable().0
+        );
+    }
+
+    #[test]
+    fn servings() {
+        assert_eq!(
+            from_servings("250mL", "8"),
+            Some(Size::amount(2000.0, Unit::Milliliters))
+        );
+        assert_eq!(
+            from_servings("31g", "6.0"),
+            Some(Size::amount(186.0, Unit::Grams))
+        );
+        assert_eq!(
+            from_servings("31g", "Approx 6"),
+            Some(Size::amount(186.0, Unit::Grams))
+        );
+        assert_eq!(from_servings("31g", ""), None);
+    }
+
+    fn pack(pack: u32, amount_is_total: bool, amount: f32, unit: Unit) -> Size {
+        Size {
+            pack,
+            amount_is_total,
+            amount,
+            unit,
+        }
+    }
+}
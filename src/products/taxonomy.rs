@@ -0,0 +1,208 @@
+use std::{collections::BTreeMap, fmt::Write, fs};
+
+use anyhow::Result;
+use serde::Serialize;
+
+use super::{tokens::Tokenizer, UpstreamProduct};
+
+pub fn main(tokenizer: &Tokenizer, products: &[UpstreamProduct]) -> Result<()> {
+    let taxonomy = Taxonomy::build(
+        tokenizer,
+        products.iter().map(|x| (x.brand.as_str(), x.name.as_str())),
+    );
+    eprintln!(
+        "{} products, {} unclassified, {} conflicted",
+        taxonomy.products, taxonomy.unclassified, taxonomy.conflicts
+    );
+
+    let mut contents = serde_json::to_string_pretty(&taxonomy)?;
+    contents.push('\n');
+    fs::write("data/products/taxonomy.json", contents)?;
+    fs::write("data/products/taxonomy.dot", taxonomy.dot(tokenizer)?)?;
+
+    Ok(())
+}
+
+#[derive(Debug, Serialize)]
+struct Taxonomy {
+    products: usize,
+    unclassified: usize,
+    conflicts: usize,
+    roots: Vec<Node>,
+}
+
+#[derive(Debug, Serialize)]
+struct Node {
+    slug: String,
+    name: String,
+    /// Products classified as exactly this token
+    products: usize,
+    /// Products classified as this token or any of its descendants
+    total: usize,
+    children: Vec<Node>,
+}
+
+impl Taxonomy {
+    fn build<'a>(
+        tokenizer: &Tokenizer,
+        products: impl Iterator<Item = (&'a str, &'a str)>,
+    ) -> Self {
+        let mut direct: BTreeMap<&str, usize> = BTreeMap::new();
+        let mut total = 0;
+        let mut unclassified = 0;
+        let mut conflicts = 0;
+        for (brand, name) in products {
+            total += 1;
+            match tokenizer.classify(brand, name) {
+                Ok(path) => match path.last() {
+                    Some(x) => *direct.entry(x).or_default() += 1,
+                    None => unclassified += 1,
+                },
+                Err(_) => conflicts += 1,
+            }
+        }
+
+        // the tree follows the first parent, as classification paths do
+        let mut children: BTreeMap<Option<&str>, Vec<(&str, String)>> = BTreeMap::new();
+        for (slug, name, parents) in tokenizer.tokens() {
+            children
+                .entry(parents.first().map(|x| x.as_str()))
+                .or_default()
+                .push((slug, name));
+        }
+
+        fn node(
+            slug: &str,
+            name: String,
+            direct: &BTreeMap<&str, usize>,
+            children: &BTreeMap<Option<&str>, Vec<(&str, String)>>,
+        ) -> Node {
+            let children: Vec<Node> = children
+                .get(&Some(slug))
+                .into_iter()
+                .flatten()
+                .map(|(slug, name)| node(slug, name.clone(), direct, children))
+                .collect();
+            let products = direct.get(slug).copied().unwrap_or_default();
+            Node {
+                slug: slug.to_string(),
+                name,
+                products,
+                total: products + children.iter().map(|x| x.total).sum::<usize>(),
+                children,
+            }
+        }
+
+        let roots = children
+            .get(&None)
+            .into_iter()
+            .flatten()
+            .map(|(slug, name)| node(slug, name.clone(), &direct, &children))
+            .collect();
+
+        Self {
+            products: total,
+            unclassified,
+            conflicts,
+            roots,
+        }
+    }
+
+    /// Renders the hierarchy for Graphviz. Secondary parents are dashed and
+    /// tokens without any products are highlighted.
+    fn dot(&self, tokenizer: &Tokenizer) -> Result<String> {
+        fn nodes(dot: &mut String, node: &Node) -> Result<()> {
+            write!(
+                dot,
+                "  \"{}\" [label=\"{}\\n{} ({})\"",
+                node.slug, node.name, node.products, node.total
+            )?;
+            if node.total == 0 {
+                write!(dot, " color=red")?;
+            }
+            writeln!(dot, "];")?;
+            for x in &node.children {
+                nodes(dot, x)?;
+            }
+            Ok(())
+        }
+
+        let mut dot = String::new();
+        writeln!(dot, "digraph taxonomy {{")?;
+        writeln!(dot, "  rankdir=LR;")?;
+        writeln!(dot, "  node [shape=box];")?;
+        for x in &self.roots {
+            nodes(&mut dot, x)?;
+        }
+        for (slug, _, parents) in tokenizer.tokens() {
+            for (i, parent) in parents.iter().enumerate() {
+                write!(dot, "  \"{parent}\" -> \"{slug}\"")?;
+                if i > 0 {
+                    write!(dot, " [style=dashed]")?;
+                }
+                writeln!(dot, ";")?;
+            }
+        }
+        writeln!(dot, "}}")?;
+
+        Ok(dot)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts() {
+        let tokenizer = Tokenizer::parse(
+            r#"
+- Drink
+- Juice .drink
+- Orange +juice
+- Apple +juice
+- Eggs
+"#,
+        )
+        .unwrap();
+        let taxonomy = Taxonomy::build(
+            &tokenizer,
+            [
+                "Nudie Orange Juice 1L",
+                "Berri Apple Juice 2L",
+                "Golden Circle Orange Juice 2L",
+                "Berri Orange & Apple Juice 2L",
+                "Coles Free Range Eggs",
+                "Coles Full Cream Milk",
+            ]
+            .into_iter()
+            .map(|x| ("", x)),
+        );
+
+        assert_eq!(taxonomy.products, 6);
+        assert_eq!(taxonomy.unclassified, 1);
+        let drink = &taxonomy.roots[0];
+        assert_eq!(
+            (drink.slug.as_str(), drink.products, drink.total),
+            ("drink", 0, 4)
+        );
+        let juice = &drink.children[0];
+        assert_eq!((juice.products, juice.total), (1, 4));
+        assert_eq!(
+            juice
+                .children
+                .iter()
+                .map(|x| (x.slug.as_str(), x.products))
+                .collect::<Vec<_>>(),
+            vec![("apple-juice", 1), ("orange-juice", 2)]
+        );
+        assert_eq!(
+            (taxonomy.roots[1].slug.as_str(), taxonomy.roots[1].total),
+            ("eggs", 1)
+        );
+
+        let dot = taxonomy.dot(&tokenizer).unwrap();
+        assert!(dot.contains("\"juice\" -> \"orange-juice\";"));
+        assert!(dot.contains("\"drink\" [label=\"Drink\\n0 (4)\"];"));
+    }
+}
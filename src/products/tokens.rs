@@ -1,16 +1,25 @@
 use std::{
-    collections::{BTreeMap, VecDeque},
+    borrow::Cow,
+    collections::{BTreeMap, BTreeSet, VecDeque},
     fs,
     path::Path,
-    str::FromStr,
 };
 
-use anyhow::{bail, Result};
+use aho_corasick::AhoCorasick;
+use anyhow::{bail, ensure, Result};
 
 #[derive(Debug)]
 struct Token {
     words: Vec<String>,
     parents: Vec<String>,
+    /// Phrases that must not appear, e.g. "milk chocolate" for milk
+    excludes: Vec<String>,
+    /// At least one of these must be the product's brand, if any are given
+    brands: Vec<String>,
+    /// Describes the product, e.g. "sugar free", rather than what it is
+    attribute: bool,
+    phrases: Vec<usize>,
+    excluded_phrases: Vec<usize>,
 }
 
 impl Token {
@@ -20,106 +29,609 @@ impl Token {
 }
 
 #[derive(Debug)]
-enum RawWord {
+pub(super) enum RawWord {
     Plain(String),
     Parent(String),
     HiddenParent(String),
+    Exclude(String),
+    Brand(String),
+    Exclusive(String),
+    Attribute,
 }
 
 impl RawWord {
-    fn parent(&self) -> Option<&str> {
+    pub(super) fn parent(&self) -> Option<&str> {
         match self {
-            Self::Plain(_) => None,
             Self::Parent(x) | Self::HiddenParent(x) => Some(x),
+            _ => None,
         }
     }
 }
 
+/// A single line of `tokens.yaml`.
+#[derive(Debug)]
+pub(super) struct Entry {
+    pub line: usize,
+    pub words: Vec<RawWord>,
+}
+
+impl Entry {
+    pub(super) fn parents(&self) -> impl Iterator<Item = &str> {
+        self.words.iter().filter_map(|x| x.parent())
+    }
+
+    /// Members of an exclusive group, if this entry is one rather than a token.
+    pub(super) fn group(&self) -> Option<Vec<&str>> {
+        let members: Vec<&str> = self
+            .words
+            .iter()
+            .filter_map(|x| match x {
+                RawWord::Exclusive(x) => Some(x.as_str()),
+                _ => None,
+            })
+            .collect();
+        (!members.is_empty()).then_some(members)
+    }
+
+    /// The slug this entry will be stored under, without resolving parents.
+    pub(super) fn slug(&self) -> String {
+        let mut parts = Vec::new();
+        for word in &self.words {
+            match word {
+                RawWord::Plain(x) => parts.push(crate::utils::slug(x)),
+                RawWord::Parent(x) => parts.push(x.clone()),
+                _ => (),
+            }
+        }
+        parts.join("-")
+    }
+}
+
+/// Reads the flat YAML list, keeping the line each entry came from.
+pub(super) fn entries(yaml: &str) -> Result<Vec<Entry>> {
+    let raw: Vec<String> = serde_yaml::from_str(yaml)?;
+    let lines: Vec<usize> = yaml
+        .lines()
+        .enumerate()
+        .filter(|(_, x)| x.starts_with('-'))
+        .map(|(i, _)| i + 1)
+        .collect();
+    ensure!(
+        raw.len() == lines.len(),
+        "tokens must be a flat list with one entry per line"
+    );
+
+    let mut output = Vec::new();
+    for (x, line) in raw.into_iter().zip(lines) {
+        let mut words = Vec::new();
+        for w in x.split(' ') {
+            if let Some(x) = w.chars().next() {
+                words.push(match x {
+                    '+' => RawWord::Parent(w[1..].to_string()),
+                    '.' => RawWord::HiddenParent(w[1..].to_string()),
+                    '-' => RawWord::Exclude(w[1..].replace('+', " ")),
+                    '@' => RawWord::Brand(w[1..].replace('+', " ")),
+                    '=' => RawWord::Exclusive(w[1..].to_string()),
+                    '~' if w.len() == 1 => RawWord::Attribute,
+                    _ => RawWord::Plain(w.replace('+', " ")),
+                });
+            }
+        }
+        output.push(Entry { line, words });
+    }
+    Ok(output)
+}
+
 pub struct Tokenizer {
     tokens: BTreeMap<String, Token>,
+    matcher: AhoCorasick,
+    /// Pattern index in `matcher` to phrase index
+    patterns: Vec<usize>,
+    /// Phrase index to the slugs of tokens that contain it
+    phrases: Vec<Vec<String>>,
+    /// Groups of tokens where only the first match is kept
+    groups: Vec<Vec<String>>,
 }
 
 impl Tokenizer {
     pub fn load(path: &Path) -> Result<Self> {
-        let raw: Vec<String> = serde_yaml::from_str(&fs::read_to_string(path)?)?;
+        Self::parse(&fs::read_to_string(path)?)
+    }
 
-        // parse words
+    pub fn parse(yaml: &str) -> Result<Self> {
         let mut queue = VecDeque::new();
-        for x in raw {
-            let mut words = Vec::new();
-            for w in x.split(' ') {
-                if let Some(x) = w.chars().next() {
-                    words.push(match x {
-                        '+' => RawWord::Parent(w[1..].to_string()),
-                        '.' => RawWord::HiddenParent(w[1..].to_string()),
-                        _ => RawWord::Plain(w.replace('+', " ")),
-                    });
+        let mut groups = Vec::new();
+        for entry in entries(yaml)? {
+            match entry.group() {
+                Some(x) => {
+                    ensure!(
+                        x.len() == entry.words.len(),
+                        "line {}: exclusive groups can't contain other words",
+                        entry.line
+                    );
+                    groups.push(x.into_iter().map(String::from).collect::<Vec<_>>());
                 }
+                None => queue.push_back(entry),
             }
-            queue.push_back(words)
         }
 
         // evaluate each token as parents are evaluated
         let mut tokens: BTreeMap<String, Token> = BTreeMap::new();
-        let mut should_stop = false;
-        loop {
+        while !queue.is_empty() {
+            let mut progress = false;
             for _ in 0..queue.len() {
-                let token = queue.pop_front().unwrap();
-
-                let mut can_solve = true;
-                for word in &token {
-                    if let Some(parent) = word.parent() {
-                        if !tokens.contains_key(parent) {
-                            if should_stop {
-                                dbg!(tokens);
-                                bail!("{token:?} references non-existent parent: {parent}")
-                            }
-                            can_solve = false;
-                        }
-                    }
+                let entry = queue.pop_front().unwrap();
+                if entry.parents().any(|x| !tokens.contains_key(x)) {
+                    queue.push_back(entry);
+                    continue;
                 }
 
-                should_stop = true;
-                if can_solve {
-                    let mut words = Vec::new();
-                    let mut parents = Vec::new();
-                    for word in token {
-                        match word {
-                            RawWord::Plain(x) => words.push(x),
-                            RawWord::Parent(x) => {
-                                words.extend(tokens.get(&x).unwrap().words.clone());
-                                parents.push(x);
-                            }
-                            RawWord::HiddenParent(x) => {
-                                parents.push(x);
-                            }
+                let mut words = Vec::new();
+                let mut parents = Vec::new();
+                let mut excludes = Vec::new();
+                let mut brands = Vec::new();
+                let mut attribute = false;
+                for word in entry.words {
+                    match word {
+                        RawWord::Plain(x) => words.push(x),
+                        RawWord::Parent(x) => {
+                            let parent = &tokens[&x];
+                            words.extend(parent.words.clone());
+                            excludes.extend(parent.excludes.clone());
+                            brands.extend(parent.brands.clone());
+                            parents.push(x);
+                        }
+                        RawWord::HiddenParent(x) => {
+                            parents.push(x);
                         }
+                        RawWord::Exclude(x) => excludes.push(x),
+                        RawWord::Brand(x) => brands.push(normalize(&x)),
+                        RawWord::Attribute => attribute = true,
+                        RawWord::Exclusive(_) => unreachable!("groups are separated above"),
                     }
-                    let token = Token { words, parents };
-                    let slug = crate::utils::slug(&token.display());
-                    tokens.insert(slug, token);
+                }
+                let token = Token {
+                    words,
+                    parents,
+                    excludes,
+                    brands,
+                    attribute,
+                    phrases: Vec::new(),
+                    excluded_phrases: Vec::new(),
+                };
+                let slug = crate::utils::slug(&token.display());
+                if tokens.insert(slug.clone(), token).is_some() {
+                    bail!("line {}: duplicate token {slug}", entry.line);
+                }
+                progress = true;
+            }
+
+            if !progress {
+                let entry = &queue[0];
+                let parent = entry.parents().find(|x| !tokens.contains_key(*x)).unwrap();
+                bail!(
+                    "line {}: references unknown or cyclic parent {parent}",
+                    entry.line
+                );
+            }
+        }
+        for x in groups.iter().flatten() {
+            ensure!(
+                tokens.contains_key(x),
+                "exclusive group has unknown token {x}"
+            );
+        }
+
+        // index every distinct word across tokens
+        let mut index: BTreeMap<String, usize> = BTreeMap::new();
+        let mut phrases: Vec<Vec<String>> = Vec::new();
+        let mut phrase = |x: &str| {
+            *index.entry(normalize_phrase(x)).or_insert_with(|| {
+                phrases.push(Vec::new());
+                phrases.len() - 1
+            })
+        };
+        for token in tokens.values_mut() {
+            token.phrases = token.words.iter().map(|x| phrase(x)).collect();
+            token.excluded_phrases = token.excludes.iter().map(|x| phrase(x)).collect();
+        }
+        for (slug, token) in &tokens {
+            for i in &token.phrases {
+                phrases[*i].push(slug.clone());
+            }
+        }
 
-                    should_stop = false;
-                } else {
-                    queue.push_back(token);
+        let (needles, patterns): (Vec<_>, Vec<_>) = index.into_iter().unzip();
+        let matcher = AhoCorasick::new(needles)?;
+
+        Ok(Self {
+            tokens,
+            matcher,
+            patterns,
+            phrases,
+            groups,
+        })
+    }
+
+    /// Every token found in the product name, including ancestors of other
+    /// matches.
+    pub fn matches(&self, brand: &str, name: &str) -> Vec<&str> {
+        let brand = normalize(brand);
+        let text = normalize(name);
+        let found: BTreeSet<usize> = self
+            .matcher
+            .find_overlapping_iter(&text)
+            .map(|x| self.patterns[x.pattern().as_usize()])
+            .collect();
+
+        let candidates: BTreeSet<&str> = found
+            .iter()
+            .flat_map(|x| self.phrases[*x].iter().map(|x| x.as_str()))
+            .collect();
+        let mut matches: Vec<&str> = candidates
+            .into_iter()
+            .filter(|x| {
+                let token = &self.tokens[*x];
+                token.phrases.iter().all(|x| found.contains(x))
+                    && !token.excluded_phrases.iter().any(|x| found.contains(x))
+                    && (token.brands.is_empty()
+                        || token
+                            .brands
+                            .iter()
+                            .any(|x| brand.contains(x.as_str()) || text.starts_with(x.as_str())))
+            })
+            .collect();
+
+        for group in &self.groups {
+            if let Some(first) = group.iter().find(|x| matches.contains(&x.as_str())) {
+                matches.retain(|x| x == first || !group.iter().any(|y| x == y));
+            }
+        }
+        matches
+    }
+
+    /// The most specific tokens found in the product name, with ancestors
+    /// removed.
+    pub fn suggest(&self, brand: &str, name: &str) -> Vec<&str> {
+        let matches = self.matches(brand, name);
+        let ancestors: BTreeSet<&str> = matches.iter().flat_map(|x| self.ancestors(x)).collect();
+        matches
+            .into_iter()
+            .filter(|x| !ancestors.contains(x))
+            .collect()
+    }
+
+    /// Resolves the product to a single path from a root token down to the most
+    /// specific match, e.g. `drink > juice > orange-juice`. Related leaves
+    /// resolve to their nearest common ancestor. Attributes don't take part.
+    pub fn classify(&self, brand: &str, name: &str) -> Result<Vec<&str>> {
+        let mut suggestions = self.suggest(brand, name);
+        suggestions.retain(|x| !self.tokens[*x].attribute);
+        let leaf = match suggestions.as_slice() {
+            [] => return Ok(Vec::new()),
+            [x] => *x,
+            [first, rest @ ..] => {
+                let mut common = self.ancestors(first);
+                for x in rest {
+                    let ancestors = self.ancestors(x);
+                    common.retain(|x| ancestors.contains(x));
+                }
+                match common.into_iter().max_by_key(|x| self.ancestors(x).len()) {
+                    Some(x) => x,
+                    None => bail!("{name} matches unrelated tokens: {suggestions:?}"),
                 }
             }
+        };
+
+        Ok(self.path(leaf))
+    }
+
+    /// Every token as its slug, display name and parents.
+    pub fn tokens(&self) -> impl Iterator<Item = (&str, String, &[String])> {
+        self.tokens
+            .iter()
+            .map(|(slug, token)| (slug.as_str(), token.display(), token.parents.as_slice()))
+    }
 
-            if queue.is_empty() {
-                break;
+    /// All ancestors of a token, following every parent.
+    fn ancestors(&self, slug: &str) -> BTreeSet<&str> {
+        let mut output = BTreeSet::new();
+        let mut queue = vec![slug];
+        while let Some(x) = queue.pop() {
+            for parent in &self.tokens[x].parents {
+                if output.insert(parent.as_str()) {
+                    queue.push(parent);
+                }
             }
         }
+        output
+    }
 
-        Ok(Self { tokens })
+    /// The path from a root token to this token, following the first parent.
+    fn path<'a>(&'a self, slug: &'a str) -> Vec<&'a str> {
+        let mut path = vec![slug];
+        let mut current = slug;
+        while let Some(parent) = self.tokens[current].parents.first() {
+            path.push(parent);
+            current = parent;
+        }
+        path.reverse();
+        path
     }
+}
 
-    pub fn suggest(&self, text: &str) -> Vec<&str> {
-        let mut suggestions = Vec::new();
-        for (slug, token) in &self.tokens {
-            if token.words.iter().all(|x| text.contains(x)) {
-                suggestions.push(&**slug);
+/// Lowercases and splits text into space-delimited words (padded at both
+/// ends) so patterns only match on word boundaries. Hyphenated words are
+/// followed by their parts, e.g. " coca-cola coca cola ", apostrophes are
+/// dropped and plurals are reduced to singular.
+pub(super) fn normalize(text: &str) -> String {
+    words(text, true)
+}
+
+/// Like [`normalize`] but keeps hyphenated words whole, for token phrases so
+/// "Coca-Cola" only matches when hyphenated.
+pub(super) fn normalize_phrase(text: &str) -> String {
+    words(text, false)
+}
+
+fn words(text: &str, parts: bool) -> String {
+    let mut output = String::from(" ");
+    let mut word = String::new();
+    for x in text.chars().chain([' ']) {
+        match x {
+            x if x.is_alphanumeric() => word.extend(x.to_lowercase()),
+            '-' if !word.is_empty() && !word.ends_with('-') => word.push('-'),
+            '\'' | '’' => (),
+            _ => {
+                let trimmed = word.trim_end_matches('-');
+                if !trimmed.is_empty() {
+                    output.push_str(&singular(trimmed));
+                    output.push(' ');
+                    if parts && trimmed.contains('-') {
+                        for x in trimmed.split('-') {
+                            output.push_str(&singular(x));
+                            output.push(' ');
+                        }
+                    }
+                }
+                word.clear();
             }
         }
-        suggestions
+    }
+    output
+}
+
+// singular forms ending in "ie" or "e" are reduced too, so "cookie" and
+// "cookies" still meet at "cooky"
+fn singular(word: &str) -> Cow<'_, str> {
+    let sibilant = |x: &str| ["ch", "sh", "x", "ss"].iter().any(|y| x.ends_with(y));
+    if word.len() <= 3 {
+        word.into()
+    } else if let Some(x) = word.strip_suffix("ies").or_else(|| word.strip_suffix("ie")) {
+        if word.len() > 4 {
+            format!("{x}y").into()
+        } else {
+            word.trim_end_matches('s').into()
+        }
+    } else if let Some(x) = word.strip_suffix("es").filter(|x| sibilant(x)) {
+        x.into()
+    } else if let Some(x) = word.strip_suffix('e').filter(|x| sibilant(x)) {
+        x.into()
+    } else if word.ends_with("ss") || word.ends_with("us") {
+        word.into()
+    } else if let Some(x) = word.strip_suffix("oes") {
+        word[..x.len() + 1].into()
+    } else if let Some(x) = word.strip_suffix('s') {
+        x.into()
+    } else {
+        word.into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOKENS: &str = r#"
+- Drink
+- Soft+Drink .drink
+- Cola +soft-drink
+- Cola .soft-drink
+- Coca-Cola .cola
+- Milk
+- Full+Cream +milk
+- Sugar Free ~
+- Zero+Sugar ~
+- Juice .drink
+- Orange +juice
+- Lemonade +soft-drink
+- Eggs
+- Free+Range +eggs
+- Tomato
+- Kirks
+"#;
+
+    fn matches(text: &str) -> Vec<String> {
+        let tokenizer = Tokenizer::parse(TOKENS).unwrap();
+        tokenizer
+            .matches("", text)
+            .into_iter()
+            .map(|x| x.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn word_boundaries() {
+        assert_eq!(matches("Milky Way Chocolate Bar"), Vec::<String>::new());
+        assert_eq!(matches("Coca-Cola Chocolate"), vec!["coca-cola", "cola"]);
+        assert_eq!(
+            matches("Pepsi Max Cola Soft Drink 1.25L"),
+            vec!["cola", "cola-soft-drink", "drink", "soft-drink"]
+        );
+        assert_eq!(
+            matches("Dairy Farmers Full Cream Milk 2L"),
+            vec!["full-cream-milk", "milk"]
+        );
+    }
+
+    #[test]
+    fn punctuation_and_plurals() {
+        assert_eq!(matches("Kirk's Lemonade"), vec!["kirks"]);
+        assert_eq!(
+            matches("Zero-Sugar Cola Soft Drink"),
+            vec![
+                "cola",
+                "cola-soft-drink",
+                "drink",
+                "soft-drink",
+                "zero-sugar"
+            ]
+        );
+        assert_eq!(
+            matches("Coles Free Range Egg"),
+            vec!["eggs", "free-range-eggs"]
+        );
+        assert_eq!(matches("Roma Tomatoes Each"), vec!["tomato"]);
+        assert_eq!(matches("SUGAR FREE LEMONADE"), vec!["sugar-free"]);
+        assert_eq!(normalize("Coca-Cola"), " coca-cola coca cola ");
+    }
+
+    #[test]
+    fn hyphenated_names() {
+        let tokenizer = Tokenizer::load(Path::new(super::super::TOKENS_PATH)).unwrap();
+        assert_eq!(
+            tokenizer.suggest("", "Sugar-Free Lemonade"),
+            vec!["sugar-free"]
+        );
+        assert_eq!(
+            tokenizer.suggest("", "Coca-Cola Zero-Sugar Soft Drink 1.25L"),
+            vec!["cola-soft-drink", "zero-sugar"]
+        );
+        assert_eq!(
+            tokenizer.suggest("", "Chocolate Flavoured Soft Drink"),
+            vec!["chocolate", "soft-drink"]
+        );
+    }
+
+    #[test]
+    fn plurals() {
+        for (plural, singular) in [
+            ("Strawberries", "Strawberry"),
+            ("Raspberries", "Raspberry"),
+            ("Peaches", "Peach"),
+            ("Dishes", "Dish"),
+            ("Boxes", "Box"),
+            ("Glasses", "Glass"),
+            ("Cookies", "Cookie"),
+            ("Quiches", "Quiche"),
+            ("Pies", "Pie"),
+            ("Tomatoes", "Tomato"),
+        ] {
+            assert_eq!(normalize(plural), normalize(singular), "{plural}");
+        }
+        assert_eq!(normalize("Strawberries"), " strawberry ");
+        assert_eq!(normalize("Peaches & Dishes"), " peach dish ");
+        assert_eq!(
+            normalize("Hummus Couscous Grass"),
+            " hummus couscous grass "
+        );
+    }
+
+    #[test]
+    fn most_specific() {
+        let tokenizer = Tokenizer::parse(TOKENS).unwrap();
+        assert_eq!(
+            tokenizer.matches("", "Nudie Orange Juice 1L"),
+            vec!["juice", "orange-juice"]
+        );
+        assert_eq!(
+            tokenizer.suggest("", "Nudie Orange Juice 1L"),
+            vec!["orange-juice"]
+        );
+        assert_eq!(
+            tokenizer.classify("", "Nudie Orange Juice 1L").unwrap(),
+            vec!["drink", "juice", "orange-juice"]
+        );
+        assert_eq!(
+            tokenizer
+                .classify("", "Schweppes Lemonade & Cola Soft Drink Mixed Pack")
+                .unwrap(),
+            vec!["drink", "soft-drink"]
+        );
+        assert_eq!(
+            tokenizer.classify("", "Birthday Candles").unwrap(),
+            Vec::<&str>::new()
+        );
+        assert!(tokenizer.classify("", "Coca-Cola Free Range Eggs").is_err());
+        assert_eq!(
+            tokenizer
+                .classify("", "Sugar Free Lemonade Soft Drink")
+                .unwrap(),
+            vec!["drink", "soft-drink", "lemonade-soft-drink"]
+        );
+        assert_eq!(
+            tokenizer.classify("", "Zero Sugar Gum").unwrap(),
+            Vec::<&str>::new()
+        );
+    }
+
+    #[test]
+    fn rules() {
+        let tokenizer = Tokenizer::parse(
+            r#"
+- Drink
+- Soft+Drink .drink
+- Pasito @Kirks .soft-drink
+- Milk -Milk+Chocolate -Coconut+Milk
+- Full+Cream +milk
+- Sugar
+- Sugar Free
+- No Sugar
+- No Added Sugar
+- =no-added-sugar =sugar-free =no-sugar =sugar
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            tokenizer.suggest("Pauls", "Pauls Full Cream Milk 2L"),
+            vec!["full-cream-milk"]
+        );
+        assert_eq!(
+            tokenizer.suggest("Cadbury", "Cadbury Dairy Milk Chocolate Block 180g"),
+            Vec::<&str>::new()
+        );
+        assert_eq!(
+            tokenizer.suggest("Ayam", "Ayam Coconut Milk 400mL"),
+            Vec::<&str>::new()
+        );
+
+        // coles names don't include the brand, woolworths names do
+        assert_eq!(
+            tokenizer.suggest("Kirks", "Pasito Passionfruit Soft Drink Cans 10 x 375mL"),
+            vec!["pasito"]
+        );
+        assert_eq!(
+            tokenizer.suggest("", "Kirks Pasito Passionfruit Soft Drink Bottle 1.25L"),
+            vec!["pasito"]
+        );
+        assert_eq!(
+            tokenizer.suggest("Coles", "Pasito Style Soft Drink 2L"),
+            vec!["soft-drink"]
+        );
+
+        assert_eq!(
+            tokenizer.suggest("", "Bickford's No Added Sugar Lime Juice Cordial 1L"),
+            vec!["no-added-sugar"]
+        );
+        assert_eq!(
+            tokenizer.suggest("", "Pepsi Max No Sugar Cola Soft Drink 1.25L"),
+            vec!["no-sugar", "soft-drink"]
+        );
+        assert_eq!(tokenizer.suggest("", "CSR White Sugar 2kg"), vec!["sugar"]);
+        assert_eq!(
+            tokenizer.suggest("", "Sugar Free Lollies 200g"),
+            vec!["sugar-free"]
+        );
     }
 }
@@ -1,5 +1,4 @@
 use std::{
-    collections::BTreeMap,
     fs::File,
     io::{BufRead, BufReader},
 };
@@ -9,10 +8,14 @@ use serde::Deserialize;
 
 use crate::Vendor;
 
-use super::UpstreamProduct;
+use super::{
+    size::{self, Coverage},
+    UpstreamProduct,
+};
 
 pub fn load() -> Result<Vec<UpstreamProduct>> {
     let mut output = Vec::new();
+    let mut coverage = Coverage::default();
     let reader = BufReader::new(zstd::Decoder::new(File::open(
         "internal/woolworths-products/raw.jsonl.zst",
     )?)?);
@@ -29,15 +32,20 @@ pub fn load() -> Result<Vec<UpstreamProduct>> {
             Some(x) => x,
             None => continue,
         };
+        let size = size::parse(&raw.package_size);
+        coverage.record(&raw.package_size, &size);
+
         output.push(UpstreamProduct {
             vendor: Vendor::Woolworths,
             id: raw.stockcode,
             brand,
             name: raw.name,
             description,
-            size: None,
+            size,
+            nutrition: None,
         })
     }
+    coverage.write(Vendor::Woolworths)?;
 
     Ok(output)
 }
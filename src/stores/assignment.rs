@@ -0,0 +1,251 @@
+use std::collections::BTreeMap;
+
+use crate::OsmId;
+
+use super::models::StoreId;
+
+// far above any sum of candidate costs, so matching more stores always wins
+const UNMATCHED: f64 = 1e6;
+const INFEASIBLE: f64 = 1e12;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Candidate<T> {
+    pub store: StoreId,
+    pub osm: OsmId,
+    /// metres
+    pub distance: f64,
+    /// lower for a likelier match
+    pub cost: f64,
+    pub detail: T,
+}
+
+pub type Assignment<T> = BTreeMap<StoreId, Candidate<T>>;
+
+/// Matches as many stores as possible, then minimises the total cost. Each
+/// connected component is solved separately, as most are a single pair.
+pub fn optimal<T: Copy>(candidates: &[Candidate<T>]) -> Assignment<T> {
+    let mut output = Assignment::new();
+    for component in components(candidates) {
+        let mut stores: Vec<StoreId> = component.iter().map(|x| x.store).collect();
+        stores.sort();
+        stores.dedup();
+        let mut elems: Vec<OsmId> = component.iter().map(|x| x.osm).collect();
+        elems.sort();
+        elems.dedup();
+
+        // a dummy column per store, for leaving it unmatched
+        let mut row = vec![INFEASIBLE; elems.len()];
+        row.resize(elems.len() + stores.len(), UNMATCHED);
+        let mut cost = vec![row; stores.len()];
+        let mut pairs = BTreeMap::new();
+        for x in &component {
+            let i = stores.binary_search(&x.store).unwrap();
+            let j = elems.binary_search(&x.osm).unwrap();
+            cost[i][j] = x.cost;
+            pairs.insert((i, j), *x);
+        }
+
+        for (i, j) in hungarian(&cost).into_iter().enumerate() {
+            if let Some(x) = pairs.get(&(i, j)) {
+                output.insert(x.store, *x);
+            }
+        }
+    }
+    output
+}
+
+/// The previous approach by distance alone, kept for comparison.
+pub fn nearest_passes<T: Copy>(candidates: &[Candidate<T>]) -> Assignment<T> {
+    let mut nearest: BTreeMap<OsmId, f64> = BTreeMap::new();
+    let mut nearby: BTreeMap<StoreId, Vec<&Candidate<T>>> = BTreeMap::new();
+    for x in candidates {
+        let d = nearest.entry(x.osm).or_insert(x.distance);
+        *d = d.min(x.distance);
+        nearby.entry(x.store).or_default().push(x);
+    }
+    for x in nearby.values_mut() {
+        // nearest last, for popping
+        x.sort_by(|a, b| b.distance.total_cmp(&a.distance));
+    }
+
+    let mut output = Assignment::new();
+    let mut keep_going = true;
+    while keep_going {
+        keep_going = false;
+        for (store, this_nearby) in nearby.iter_mut() {
+            if let Some(x) = this_nearby.pop() {
+                keep_going = true;
+                if nearest[&x.osm] == x.distance && !output.contains_key(store) {
+                    output.insert(*store, *x);
+                }
+            }
+        }
+    }
+    output
+}
+
+fn components<T: Copy>(candidates: &[Candidate<T>]) -> Vec<Vec<Candidate<T>>> {
+    let mut stores: BTreeMap<StoreId, usize> = BTreeMap::new();
+    let mut elems: BTreeMap<OsmId, usize> = BTreeMap::new();
+    let mut parent: Vec<usize> = Vec::new();
+    let mut nodes = Vec::new();
+    for x in candidates {
+        let a = node(&mut parent, stores.get(&x.store).copied());
+        stores.insert(x.store, a);
+        let b = node(&mut parent, elems.get(&x.osm).copied());
+        elems.insert(x.osm, b);
+        let (a, b) = (root(&mut parent, a), root(&mut parent, b));
+        parent[a] = b;
+        nodes.push(b);
+    }
+
+    let mut output: BTreeMap<usize, Vec<Candidate<T>>> = BTreeMap::new();
+    for (x, node) in candidates.iter().zip(nodes) {
+        output.entry(root(&mut parent, node)).or_default().push(*x);
+    }
+    output.into_values().collect()
+}
+
+fn node(parent: &mut Vec<usize>, existing: Option<usize>) -> usize {
+    existing.unwrap_or_else(|| {
+        parent.push(parent.len());
+        parent.len() - 1
+    })
+}
+
+fn root(parent: &mut [usize], mut x: usize) -> usize {
+    while parent[x] != x {
+        parent[x] = parent[parent[x]];
+        x = parent[x];
+    }
+    x
+}
+
+/// The column of each row in a minimum cost assignment, for a matrix with at
+/// least as many columns as rows.
+fn hungarian(cost: &[Vec<f64>]) -> Vec<usize> {
+    let n = cost.len();
+    let m = cost.first().map_or(0, Vec::len);
+    // potentials and matches are 1-indexed, with 0 as a sentinel
+    let mut u = vec![0.0; n + 1];
+    let mut v = vec![0.0; m + 1];
+    let mut row_of = vec![0usize; m + 1];
+    let mut way = vec![0usize; m + 1];
+    for i in 1..=n {
+        row_of[0] = i;
+        let mut j0 = 0;
+        let mut min = vec![f64::INFINITY; m + 1];
+        let mut used = vec![false; m + 1];
+        loop {
+            used[j0] = true;
+            let i0 = row_of[j0];
+            let mut delta = f64::INFINITY;
+            let mut j1 = 0;
+            for j in 1..=m {
+                if used[j] {
+                    continue;
+                }
+                let x = cost[i0 - 1][j - 1] - u[i0] - v[j];
+                if x < min[j] {
+                    min[j] = x;
+                    way[j] = j0;
+                }
+                if min[j] < delta {
+                    delta = min[j];
+                    j1 = j;
+                }
+            }
+            for j in 0..=m {
+                if used[j] {
+                    u[row_of[j]] += delta;
+                    v[j] -= delta;
+                } else {
+                    min[j] -= delta;
+                }
+            }
+            j0 = j1;
+            if row_of[j0] == 0 {
+                break;
+            }
+        }
+        while j0 != 0 {
+            let j1 = way[j0];
+            row_of[j0] = row_of[j1];
+            j0 = j1;
+        }
+    }
+
+    let mut output = vec![0; n];
+    for (j, i) in row_of.into_iter().enumerate().skip(1) {
+        if i != 0 {
+            output[i - 1] = j - 1;
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidate(store: u32, osm: u64, distance: f64) -> Candidate<()> {
+        Candidate {
+            store: StoreId::Coles(store),
+            osm: OsmId::Node(osm),
+            distance,
+            cost: distance,
+            detail: (),
+        }
+    }
+
+    fn pairs(x: &Assignment<()>) -> Vec<(u32, OsmId)> {
+        x.values()
+            .map(|x| (x.store.coles().unwrap(), x.osm))
+            .collect()
+    }
+
+    #[test]
+    fn beats_nearest_passes() {
+        let candidates = [
+            // store 1 is nearest to both elements, store 2 only reaches 10
+            candidate(1, 10, 50.0),
+            candidate(1, 11, 60.0),
+            candidate(2, 10, 80.0),
+            // a separate component
+            candidate(3, 12, 300.0),
+        ];
+        let before = nearest_passes(&candidates);
+        assert_eq!(before.len(), 2);
+        assert!(!before.contains_key(&StoreId::Coles(2)));
+
+        let after = optimal(&candidates);
+        assert_eq!(
+            pairs(&after),
+            [
+                (1, OsmId::Node(11)),
+                (2, OsmId::Node(10)),
+                (3, OsmId::Node(12))
+            ]
+        );
+    }
+
+    #[test]
+    fn minimises_distance() {
+        let candidates = [
+            candidate(1, 10, 100.0),
+            candidate(1, 11, 10.0),
+            candidate(2, 10, 20.0),
+            candidate(2, 11, 30.0),
+            // more stores than elements
+            candidate(3, 11, 5.0),
+        ];
+        let after = optimal(&candidates);
+        assert_eq!(after.len(), 2);
+        let total: f64 = after.values().map(|x| x.cost).sum();
+        assert_eq!(total, 25.0);
+        assert_eq!(
+            hungarian(&[vec![4.0, 1.0, 3.0], vec![2.0, 0.0, 5.0]]),
+            [1, 0]
+        );
+    }
+}
@@ -0,0 +1,290 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Write,
+    fs::{self, OpenOptions},
+    io::Write as _,
+    path::Path,
+};
+
+use anyhow::Result;
+
+use crate::{prices, utils::today, OsmId, Vendor};
+
+use super::{models::StoreId, Store};
+
+const CHANGELOG_DIR: &str = "data/stores/changelog";
+
+// metres
+const MOVED_DISTANCE: f64 = 100.0;
+
+/// Writes a dated changelog of what changed between two builds, if anything.
+pub fn main(previous: &[Store], current: &[Store]) -> Result<()> {
+    let mut feeds = BTreeMap::new();
+    for vendor in Vendor::all() {
+        feeds.insert(vendor, Feeds::load(vendor)?);
+    }
+
+    let md = changes(previous, current, &feeds)?;
+    if md.is_empty() {
+        eprintln!("No store changes since the last build");
+        return Ok(());
+    }
+    fs::create_dir_all(CHANGELOG_DIR)?;
+    let path = format!("{CHANGELOG_DIR}/{}.md", today());
+    append(Path::new(&path), &md)?;
+    eprintln!("Wrote store changes to {path}");
+
+    Ok(())
+}
+
+// a day can have several builds, each under its own heading
+fn append(path: &Path, md: &str) -> Result<()> {
+    let existing = if path.exists() {
+        fs::read_to_string(path)?
+    } else {
+        String::new()
+    };
+    let build = existing
+        .lines()
+        .filter(|x| x.starts_with("# Build "))
+        .count()
+        + 1;
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    if !existing.is_empty() {
+        writeln!(file)?;
+    }
+    write!(file, "# Build {build}\n\n{md}")?;
+    Ok(())
+}
+
+/// The stores in a vendor's two latest price snapshots.
+#[derive(Debug, Default)]
+pub struct Feeds {
+    latest: Option<(String, BTreeSet<u32>)>,
+    previous: Option<(String, BTreeSet<u32>)>,
+}
+
+impl Feeds {
+    fn load(vendor: Vendor) -> Result<Self> {
+        let mut output = Self::default();
+        let snapshots = prices::snapshots(vendor)?;
+        let mut snapshots = snapshots.iter().rev();
+        if let Some(x) = snapshots.next() {
+            output.latest = Some((x.clone(), prices::load_index(x, vendor)?.stores));
+        }
+        if let Some(x) = snapshots.next() {
+            output.previous = Some((x.clone(), prices::load_index(x, vendor)?.stores));
+        }
+        Ok(output)
+    }
+
+    /// The latest snapshot, if the store was in the one before but not it.
+    fn vanished(&self, id: u32) -> Option<&str> {
+        let (latest, stores) = self.latest.as_ref()?;
+        let (_, before) = self.previous.as_ref()?;
+        (before.contains(&id) && !stores.contains(&id)).then_some(latest.as_str())
+    }
+
+    fn carries(&self, id: u32) -> bool {
+        self.latest.as_ref().is_some_and(|(_, x)| x.contains(&id))
+    }
+}
+
+fn raw_id(id: StoreId) -> u32 {
+    match id {
+        StoreId::Coles(x) | StoreId::Woolworths(x) => x,
+    }
+}
+
+/// Compares builds by `StoreId`, and by `OsmId` to tell a store given a new
+/// id from one that closed. Empty if nothing changed.
+fn changes(
+    previous: &[Store],
+    current: &[Store],
+    feeds: &BTreeMap<Vendor, Feeds>,
+) -> Result<String> {
+    let before: BTreeMap<StoreId, &Store> = previous.iter().map(|x| (x.id, x)).collect();
+    let after: BTreeMap<StoreId, &Store> = current.iter().map(|x| (x.id, x)).collect();
+    let before_osm: BTreeMap<OsmId, &Store> = previous.iter().map(|x| (x.osm, x)).collect();
+    let after_osm: BTreeMap<OsmId, &Store> = current.iter().map(|x| (x.osm, x)).collect();
+    let carries = |id: StoreId| {
+        feeds
+            .get(&id.vendor())
+            .is_some_and(|x| x.carries(raw_id(id)))
+    };
+
+    let mut changes = Vec::new();
+    let (mut opened, mut closed, mut renumbered) = (0, 0, 0);
+    for (id, x) in &after {
+        if before.contains_key(id) {
+            continue;
+        }
+        match before_osm
+            .get(&x.osm)
+            .filter(|old| !after.contains_key(&old.id))
+        {
+            Some(old) => {
+                renumbered += 1;
+                changes.push(format!("- renumbered {:?} to {id:?} {}", old.id, x.name));
+            }
+            None => {
+                opened += 1;
+                changes.push(format!("- opened {id:?} {}, {}", x.name, x.desc));
+            }
+        }
+    }
+    for (id, x) in &before {
+        if after.contains_key(id) {
+            continue;
+        }
+        if after_osm
+            .get(&x.osm)
+            .is_some_and(|new| !before.contains_key(&new.id))
+        {
+            // reported as renumbered
+            continue;
+        }
+        closed += 1;
+        let note = if carries(*id) {
+            ", but still in price feeds"
+        } else {
+            ""
+        };
+        changes.push(format!("- closed {id:?} {}, {}{note}", x.name, x.desc));
+    }
+
+    let (mut renamed, mut moved) = (0, 0);
+    for (id, new) in &after {
+        let Some(old) = before.get(id) else {
+            continue;
+        };
+        if old.name != new.name {
+            renamed += 1;
+            changes.push(format!("- renamed {id:?} {} to {}", old.name, new.name));
+        }
+        if old.desc != new.desc {
+            changes.push(format!(
+                "- {id:?} {} changed from {} to {}",
+                new.name, old.desc, new.desc
+            ));
+        }
+        let d = old.position.distance(&new.position);
+        if d > MOVED_DISTANCE || old.osm != new.osm {
+            moved += 1;
+            changes.push(format!(
+                "- moved {id:?} {} by {d:.0}m, {:?} to {:?}",
+                new.name, old.osm, new.osm
+            ));
+        }
+    }
+
+    let mut todo = Vec::new();
+    for (id, x) in &after {
+        if let Some(snapshot) = feeds
+            .get(&id.vendor())
+            .and_then(|feeds| feeds.vanished(raw_id(*id)))
+        {
+            todo.push(format!(
+                "- {id:?} {} is missing from {snapshot} prices, likely closed",
+                x.name
+            ));
+        }
+    }
+
+    if changes.is_empty() && todo.is_empty() {
+        return Ok(String::new());
+    }
+    let mut md = String::new();
+    writeln!(md, "## Statistics\n")?;
+    writeln!(md, "- {} stores, {} before", current.len(), previous.len())?;
+    writeln!(
+        md,
+        "- {opened} opened, {closed} closed, {renumbered} renumbered"
+    )?;
+    writeln!(md, "- {renamed} renamed, {moved} moved")?;
+    writeln!(md)?;
+    if !changes.is_empty() {
+        writeln!(md, "## Changes\n")?;
+        writeln!(md, "{}\n", changes.join("\n"))?;
+    }
+    if !todo.is_empty() {
+        writeln!(md, "## Todo\n")?;
+        writeln!(md, "{}\n", todo.join("\n"))?;
+    }
+    Ok(md)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stores::models::LatLon;
+
+    fn store(id: u32, osm: u64, name: &str, lat: f64) -> Store {
+        Store {
+            id: StoreId::Coles(id),
+            name: name.to_string(),
+            desc: "Sydney NSW 2000".to_string(),
+            osm: OsmId::Node(osm),
+            centre: None,
+            state: "NSW".to_string(),
+            position: LatLon::new(lat, 151.2),
+        }
+    }
+
+    #[test]
+    fn changelog() {
+        let previous = [
+            store(1, 100001, "Coles Central", -33.8),
+            store(2, 100002, "Coles Town Hall", -33.8),
+            store(3, 100003, "Coles Wynyard", -33.8),
+            store(4, 100004, "Coles Redfern", -33.8),
+        ];
+        let current = [
+            store(1, 100001, "Coles Central Station", -33.8),
+            // about 222m
+            store(2, 100002, "Coles Town Hall", -33.802),
+            store(30, 100003, "Coles Wynyard", -33.8),
+            store(5, 100005, "Coles Local Newtown", -33.9),
+        ];
+        let feeds = BTreeMap::from([(
+            Vendor::Coles,
+            Feeds {
+                latest: Some(("2026-10-19".to_string(), BTreeSet::from([1, 4, 5, 30]))),
+                previous: Some(("2026-10-12".to_string(), BTreeSet::from([1, 2, 3, 4]))),
+            },
+        )]);
+
+        let md = changes(&previous, &current, &feeds).unwrap();
+        let lines: Vec<&str> = md.lines().collect();
+        assert_eq!(lines[3], "- 1 opened, 1 closed, 1 renumbered");
+        assert_eq!(lines[4], "- 1 renamed, 1 moved");
+        let changes_start = lines.iter().position(|x| *x == "## Changes").unwrap();
+        let kinds: Vec<&str> = lines[changes_start + 2..]
+            .iter()
+            .take_while(|x| !x.is_empty())
+            .map(|x| x.split(' ').nth(1).unwrap())
+            .collect();
+        assert_eq!(
+            kinds,
+            ["opened", "renumbered", "closed", "renamed", "moved"]
+        );
+        assert!(md.contains("Coles Redfern, Sydney NSW 2000, but still in price feeds"));
+        assert!(md.contains("by 222m"));
+        assert!(md.contains("Coles Town Hall is missing from 2026-10-19 prices, likely closed"));
+
+        assert_eq!(changes(&previous, &previous, &BTreeMap::new()).unwrap(), "");
+    }
+
+    #[test]
+    fn appends_builds() {
+        let path = std::env::temp_dir().join(format!("stores-changelog-{}.md", std::process::id()));
+        let _ = fs::remove_file(&path);
+        append(&path, "## Statistics\n\n- first\n").unwrap();
+        append(&path, "## Statistics\n\n- second\n").unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "# Build 1\n\n## Statistics\n\n- first\n\n# Build 2\n\n## Statistics\n\n- second\n"
+        );
+        fs::remove_file(&path).unwrap();
+    }
+}
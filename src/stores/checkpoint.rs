@@ -0,0 +1,120 @@
+use std::{
+    fs,
+    ops::{Deref, DerefMut},
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use serde::Serialize;
+
+/// Minimum time between writes while a cache is being filled.
+const INTERVAL: Duration = Duration::from_secs(10);
+
+/// A cache being filled by slow requests, written every so often and
+/// whenever it's dropped with unsaved changes, so a run that fails part way
+/// keeps what it fetched.
+pub struct Checkpoint<'a, T: Serialize> {
+    path: &'a Path,
+    raw: &'a mut T,
+    saved: Instant,
+    dirty: bool,
+}
+
+impl<'a, T: Serialize> Checkpoint<'a, T> {
+    pub fn new(path: &'a Path, raw: &'a mut T) -> Self {
+        Self {
+            path,
+            raw,
+            saved: Instant::now(),
+            dirty: false,
+        }
+    }
+
+    /// Writes the cache if it's been a while since the last write.
+    pub fn tick(&mut self) -> Result<()> {
+        if self.dirty && self.saved.elapsed() >= INTERVAL {
+            self.save()?;
+        }
+        Ok(())
+    }
+
+    /// Writes the cache, for when it's complete.
+    pub fn finish(mut self) -> Result<()> {
+        self.save()
+    }
+
+    fn save(&mut self) -> Result<()> {
+        // renamed into place, so a run killed mid-write can't truncate it
+        let mut tmp = self.path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        fs::write(&tmp, serde_json::to_string_pretty(&*self.raw)?)?;
+        fs::rename(&tmp, self.path)?;
+        self.saved = Instant::now();
+        self.dirty = false;
+        Ok(())
+    }
+}
+
+impl<T: Serialize> Deref for Checkpoint<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.raw
+    }
+}
+
+impl<T: Serialize> DerefMut for Checkpoint<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.dirty = true;
+        self.raw
+    }
+}
+
+impl<T: Serialize> Drop for Checkpoint<'_, T> {
+    fn drop(&mut self) {
+        if !self.dirty {
+            return;
+        }
+        match self.save() {
+            Ok(()) => eprintln!("Saved partial results to {}", self.path.display()),
+            Err(e) => eprintln!(
+                "Failed to save partial results to {}: {e}",
+                self.path.display()
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+
+    #[test]
+    fn saves_on_failure() {
+        let path =
+            std::env::temp_dir().join(format!("stores-checkpoint-{}.json", std::process::id()));
+        let mut raw = BTreeMap::new();
+
+        let fetch = |raw: &mut BTreeMap<u32, u32>| -> Result<()> {
+            let mut checkpoint = Checkpoint::new(&path, raw);
+            for x in 0..5 {
+                if x == 3 {
+                    anyhow::bail!("request failed");
+                }
+                checkpoint.insert(x, x * 10);
+                checkpoint.tick()?;
+            }
+            checkpoint.finish()
+        };
+        assert!(fetch(&mut raw).is_err());
+
+        let saved: BTreeMap<u32, u32> =
+            serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(saved, BTreeMap::from([(0, 0), (1, 10), (2, 20)]));
+        assert!(!path.with_extension("json.tmp").exists());
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,272 @@
+use anyhow::Result;
+use flatbuffers::{FlatBufferBuilder, WIPOffset};
+use serde::Serialize;
+
+use crate::OsmId;
+
+use super::{models::StoreId, Store};
+
+pub const GEOJSON_PATH: &str = "data/stores/output.geojson";
+pub const FLATGEOBUF_PATH: &str = "data/stores/output.fgb";
+
+#[derive(Serialize)]
+#[serde(tag = "type")]
+struct FeatureCollection<'a> {
+    features: Vec<Feature<'a>>,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type")]
+struct Feature<'a> {
+    properties: Properties<'a>,
+    geometry: Geometry,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type")]
+enum Geometry {
+    Point { coordinates: [f64; 2] },
+}
+
+#[derive(Serialize)]
+struct Properties<'a> {
+    #[serde(flatten)]
+    id: StoreId,
+    name: &'a str,
+    desc: &'a str,
+    osm: OsmId,
+    centre: Option<&'a str>,
+    state: &'a str,
+}
+
+impl<'a> From<&'a Store> for Properties<'a> {
+    fn from(x: &'a Store) -> Self {
+        Self {
+            id: x.id,
+            name: &x.name,
+            desc: &x.desc,
+            osm: x.osm,
+            centre: x.centre.as_deref(),
+            state: &x.state,
+        }
+    }
+}
+
+pub fn geojson(stores: &[Store]) -> Result<String> {
+    let collection = FeatureCollection {
+        features: stores
+            .iter()
+            .map(|x| Feature {
+                properties: x.into(),
+                geometry: Geometry::Point {
+                    coordinates: [x.position.lon, x.position.lat],
+                },
+            })
+            .collect(),
+    };
+    let mut contents = serde_json::to_string_pretty(&collection)?;
+    contents.push('\n');
+    Ok(contents)
+}
+
+// See https://github.com/flatgeobuf/flatgeobuf/tree/master/src/fbs for the
+// schemas. Field slots are 4 + 2 * the field's index in its table.
+const MAGIC: [u8; 8] = *b"fgb\x03fgb\x00";
+const GEOMETRY_POINT: u8 = 1;
+const COLUMN_UINT: u8 = 6;
+const COLUMN_STRING: u8 = 11;
+const WGS84: i32 = 4326;
+
+/// Property columns, in the order their values are written.
+const COLUMNS: [(&str, u8); 7] = [
+    ("vendor", COLUMN_STRING),
+    ("id", COLUMN_UINT),
+    ("name", COLUMN_STRING),
+    ("desc", COLUMN_STRING),
+    ("osm", COLUMN_STRING),
+    ("centre", COLUMN_STRING),
+    ("state", COLUMN_STRING),
+];
+
+/// Encodes stores as FlatGeobuf, without a spatial index since the whole
+/// file is small enough to read at once.
+pub fn flatgeobuf(stores: &[Store]) -> Vec<u8> {
+    let mut output = MAGIC.to_vec();
+    output.extend(header(stores));
+    for x in stores {
+        output.extend(feature(x));
+    }
+    output
+}
+
+fn header(stores: &[Store]) -> Vec<u8> {
+    let mut fbb = FlatBufferBuilder::new();
+
+    let name = fbb.create_string("stores");
+    let envelope = fbb.create_vector(&envelope(stores));
+    let columns: Vec<_> = COLUMNS
+        .iter()
+        .map(|(name, kind)| {
+            let name = fbb.create_string(name);
+            let start = fbb.start_table();
+            fbb.push_slot_always(4, name);
+            fbb.push_slot_always(6, *kind);
+            fbb.end_table(start)
+        })
+        .collect();
+    let columns = fbb.create_vector(&columns);
+
+    let org = fbb.create_string("EPSG");
+    let start = fbb.start_table();
+    fbb.push_slot_always(4, org);
+    fbb.push_slot_always(6, WGS84);
+    let crs = fbb.end_table(start);
+
+    let start = fbb.start_table();
+    fbb.push_slot_always(4, name);
+    fbb.push_slot_always(6, envelope);
+    fbb.push_slot_always(8, GEOMETRY_POINT);
+    fbb.push_slot_always(18, columns);
+    fbb.push_slot_always(20, stores.len() as u64);
+    // no index
+    fbb.push_slot_always(22, 0u16);
+    fbb.push_slot_always(24, crs);
+    let root = fbb.end_table(start);
+
+    fbb.finish_size_prefixed(root, None);
+    fbb.finished_data().to_vec()
+}
+
+/// The bounding box as min x, min y, max x, max y.
+fn envelope(stores: &[Store]) -> Vec<f64> {
+    if stores.is_empty() {
+        return Vec::new();
+    }
+    let mut output = vec![f64::MAX, f64::MAX, f64::MIN, f64::MIN];
+    for x in stores {
+        let (lon, lat) = (x.position.lon, x.position.lat);
+        output[0] = output[0].min(lon);
+        output[1] = output[1].min(lat);
+        output[2] = output[2].max(lon);
+        output[3] = output[3].max(lat);
+    }
+    output
+}
+
+fn feature(store: &Store) -> Vec<u8> {
+    let mut fbb = FlatBufferBuilder::new();
+
+    let xy = fbb.create_vector(&[store.position.lon, store.position.lat]);
+    let start = fbb.start_table();
+    fbb.push_slot_always(6, xy);
+    fbb.push_slot_always(16, GEOMETRY_POINT);
+    let geometry = fbb.end_table(start);
+
+    let properties = fbb.create_vector(&properties(store));
+    let start = fbb.start_table();
+    fbb.push_slot_always(4, geometry);
+    fbb.push_slot_always(6, properties);
+    let root: WIPOffset<_> = fbb.end_table(start);
+
+    fbb.finish_size_prefixed(root, None);
+    fbb.finished_data().to_vec()
+}
+
+/// Column index then value for every property present, little endian with
+/// strings prefixed by their length.
+fn properties(store: &Store) -> Vec<u8> {
+    let id = match store.id {
+        StoreId::Coles(x) | StoreId::Woolworths(x) => x,
+    };
+    let osm = store.osm.to_string();
+    let values: [Option<Value>; 7] = [
+        Some(Value::String(store.id.vendor().slug())),
+        Some(Value::UInt(id)),
+        Some(Value::String(&store.name)),
+        Some(Value::String(&store.desc)),
+        Some(Value::String(&osm)),
+        store.centre.as_deref().map(Value::String),
+        Some(Value::String(&store.state)),
+    ];
+
+    let mut output = Vec::new();
+    for (i, value) in values.into_iter().enumerate() {
+        let Some(value) = value else {
+            continue;
+        };
+        output.extend((i as u16).to_le_bytes());
+        match value {
+            Value::UInt(x) => output.extend(x.to_le_bytes()),
+            Value::String(x) => {
+                output.extend((x.len() as u32).to_le_bytes());
+                output.extend(x.as_bytes());
+            }
+        }
+    }
+    output
+}
+
+enum Value<'a> {
+    UInt(u32),
+    String(&'a str),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stores::models::LatLon;
+
+    fn store() -> Store {
+        Store {
+            id: StoreId::Coles(205),
+            name: "Coles Karrinyup".to_string(),
+            desc: "Karrinyup Shopping Centre, Karrinyup WA 6018".to_string(),
+            osm: "n11090786327".parse().unwrap(),
+            centre: Some("Karrinyup Shopping Centre".to_string()),
+            state: "WA".to_string(),
+            position: LatLon::new(-31.8775736, 115.7776645),
+        }
+    }
+
+    #[test]
+    fn geojson_feature() {
+        let x: serde_json::Value = serde_json::from_str(&geojson(&[store()]).unwrap()).unwrap();
+        assert_eq!(x["type"], "FeatureCollection");
+        let feature = &x["features"][0];
+        assert_eq!(
+            feature["geometry"],
+            serde_json::json!({"type": "Point", "coordinates": [115.7776645, -31.8775736]})
+        );
+        assert_eq!(
+            feature["properties"],
+            serde_json::json!({
+                "vendor": "coles",
+                "id": 205,
+                "name": "Coles Karrinyup",
+                "desc": "Karrinyup Shopping Centre, Karrinyup WA 6018",
+                "osm": "n11090786327",
+                "centre": "Karrinyup Shopping Centre",
+                "state": "WA",
+            })
+        );
+    }
+
+    #[test]
+    fn flatgeobuf_layout() {
+        let mut x = store();
+        x.centre = None;
+        let bytes = flatgeobuf(&[x.clone()]);
+        assert_eq!(bytes[..8], MAGIC);
+        let header_len = u32::from_le_bytes(bytes[8..12].try_into().unwrap()) as usize;
+        let feature = &bytes[12 + header_len..];
+        let feature_len = u32::from_le_bytes(feature[..4].try_into().unwrap()) as usize;
+        assert_eq!(feature.len(), 4 + feature_len);
+
+        let properties = properties(&x);
+        // vendor string, then the id as a uint
+        assert_eq!(properties[..11], *b"\x00\x00\x05\x00\x00\x00coles");
+        assert_eq!(properties[11..17], [1, 0, 205, 0, 0, 0]);
+        // centre is skipped, so state follows osm
+        assert!(properties.ends_with(b"\x06\x00\x02\x00\x00\x00WA"));
+    }
+}
@@ -0,0 +1,203 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufRead, BufReader},
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use anyhow::{bail, Context, Result};
+use rstar::{primitives::GeomWithData, RTree};
+
+use super::{gnaf_addrs::GNAFAddress, models::LatLon};
+
+/// Accepted names for each column, compared case-insensitively. The first
+/// are those of G-NAF's `ADDRESS_VIEW`.
+const LATITUDE: [&str; 2] = ["LATITUDE", "LAT"];
+const LONGITUDE: [&str; 3] = ["LONGITUDE", "LON", "LNG"];
+const LOCALITY: [&str; 2] = ["LOCALITY_NAME", "LOCALITY"];
+const POSTCODE: [&str; 1] = ["POSTCODE"];
+const STATE: [&str; 2] = ["STATE_ABBREVIATION", "STATE"];
+
+static EXTRACT: OnceLock<PathBuf> = OnceLock::new();
+static GEOCODER: OnceLock<Geocoder> = OnceLock::new();
+
+/// Uses the extract for the rest of the run, if one is given.
+pub fn init(extract: Option<PathBuf>) {
+    if let Some(x) = extract {
+        let _ = EXTRACT.set(x);
+    }
+}
+
+/// The local geocoder if an extract was given, loaded on first use as it
+/// takes a while for the whole country.
+pub fn local() -> Result<Option<&'static Geocoder>> {
+    let Some(path) = EXTRACT.get() else {
+        return Ok(None);
+    };
+    if GEOCODER.get().is_none() {
+        let _ = GEOCODER.set(Geocoder::load(path)?);
+    }
+    Ok(GEOCODER.get())
+}
+
+/// Finds the nearest G-NAF address to a point, from an extract with one
+/// address per row.
+pub struct Geocoder {
+    /// Points on the unit sphere, so the nearest by straight line distance
+    /// is also the nearest along the surface
+    tree: RTree<GeomWithData<[f64; 3], usize>>,
+    positions: Vec<(LatLon, usize)>,
+    /// Locality, postcode and state, shared by many addresses
+    areas: Vec<(String, String, String)>,
+}
+
+impl Geocoder {
+    /// Loads a pipe separated (`.psv`) or comma separated extract.
+    pub fn load(path: &Path) -> Result<Self> {
+        eprintln!("Loading G-NAF addresses from {}...", path.display());
+        let delimiter = match path.extension().and_then(|x| x.to_str()) {
+            Some("psv") => '|',
+            _ => ',',
+        };
+        let file =
+            File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+        let output = Self::parse(BufReader::new(file), delimiter)
+            .with_context(|| format!("Failed to load {}", path.display()))?;
+        eprintln!("Loaded {} addresses", output.positions.len());
+        Ok(output)
+    }
+
+    pub fn parse(reader: impl BufRead, delimiter: char) -> Result<Self> {
+        let mut lines = reader.lines();
+        let Some(header) = lines.next() else {
+            bail!("Missing header row");
+        };
+        let header = split(&header?, delimiter);
+        let column = |names: &[&str]| {
+            header
+                .iter()
+                .position(|x| names.iter().any(|name| x.eq_ignore_ascii_case(name)))
+                .with_context(|| format!("Missing a column named any of {names:?}"))
+        };
+        let lat = column(&LATITUDE)?;
+        let lon = column(&LONGITUDE)?;
+        let locality = column(&LOCALITY)?;
+        let postcode = column(&POSTCODE)?;
+        let state = column(&STATE)?;
+
+        let mut positions = Vec::new();
+        let mut areas = Vec::new();
+        let mut area_ids = HashMap::new();
+        for line in lines {
+            let row = split(&line?, delimiter);
+            let field = |i: usize| row.get(i).map_or("", |x| x.trim());
+            // some addresses are yet to be geocoded
+            let (Ok(lat), Ok(lon)) = (field(lat).parse(), field(lon).parse()) else {
+                continue;
+            };
+            let area = (
+                field(locality).to_string(),
+                field(postcode).to_string(),
+                field(state).to_string(),
+            );
+            let id = *area_ids.entry(area.clone()).or_insert_with(|| {
+                areas.push(area);
+                areas.len() - 1
+            });
+            positions.push((LatLon::new(lat, lon), id));
+        }
+
+        let tree = RTree::bulk_load(
+            positions
+                .iter()
+                .enumerate()
+                .map(|(i, (x, _))| GeomWithData::new(unit_vector(*x), i))
+                .collect(),
+        );
+        Ok(Self {
+            tree,
+            positions,
+            areas,
+        })
+    }
+
+    /// The address nearest to `position`, or `None` if there are none.
+    pub fn nearest(&self, position: LatLon) -> Option<GNAFAddress> {
+        let nearest = self.tree.nearest_neighbor(&unit_vector(position))?;
+        let (position, area) = self.positions[nearest.data];
+        let (locality, postcode, state) = self.areas[area].clone();
+        Some(GNAFAddress {
+            latitude: position.lat,
+            longitude: position.lon,
+            locality,
+            postcode,
+            state,
+        })
+    }
+}
+
+fn unit_vector(x: LatLon) -> [f64; 3] {
+    let (lat, lon) = (x.lat.to_radians(), x.lon.to_radians());
+    [lat.cos() * lon.cos(), lat.cos() * lon.sin(), lat.sin()]
+}
+
+/// Splits a row, allowing quoted fields with `""` for a quote.
+fn split(line: &str, delimiter: char) -> Vec<String> {
+    let mut output = vec![String::new()];
+    let mut quoted = false;
+    let mut chars = line.trim_end_matches('\r').chars().peekable();
+    while let Some(x) = chars.next() {
+        let field = output.last_mut().expect("never empty");
+        match x {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            '"' if quoted || field.is_empty() => quoted = !quoted,
+            x if x == delimiter && !quoted => output.push(String::new()),
+            x => field.push(x),
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nearest() {
+        let psv = "\
+ADDRESS_DETAIL_PID|LOCALITY_NAME|STATE_ABBREVIATION|POSTCODE|LATITUDE|LONGITUDE
+GANSW1|SYDNEY|NSW|2000|-33.8688|151.2093
+GANSW2|SURRY HILLS|NSW|2010|-33.8861|151.2111
+GANSW3|SURRY HILLS|NSW|2010||
+GAVIC1|MELBOURNE|VIC|3000|-37.8136|144.9631
+";
+        let geocoder = Geocoder::parse(psv.as_bytes(), '|').unwrap();
+        assert_eq!(geocoder.positions.len(), 3);
+        assert_eq!(geocoder.areas.len(), 3);
+
+        let x = geocoder.nearest(LatLon::new(-33.884, 151.21)).unwrap();
+        assert_eq!(
+            (x.locality.as_str(), x.postcode.as_str(), x.state.as_str()),
+            ("SURRY HILLS", "2010", "NSW")
+        );
+        assert_eq!(x.latitude, -33.8861);
+        let x = geocoder.nearest(LatLon::new(-38.0, 145.0)).unwrap();
+        assert_eq!(x.locality, "MELBOURNE");
+
+        assert!(Geocoder::parse("LAT,LON\n".as_bytes(), ',').is_err());
+    }
+
+    #[test]
+    fn csv() {
+        let csv =
+            "locality,postcode,state,lat,lon\r\n\"WEST END, BRISBANE\",4101,QLD,-27.48,153.01\r\n";
+        let geocoder = Geocoder::parse(csv.as_bytes(), ',').unwrap();
+        let x = geocoder.nearest(LatLon::new(-27.5, 153.0)).unwrap();
+        assert_eq!(x.locality, "WEST END, BRISBANE");
+        assert_eq!(split(r#"a,"b ""c""",d"#, ','), ["a", r#"b "c""#, "d"]);
+    }
+}
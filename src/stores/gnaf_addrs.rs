@@ -1,13 +1,17 @@
 use std::{collections::BTreeMap, fs, path::PathBuf};
 
-use anyhow::Result;
-use indicatif::ProgressBar;
+use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
-use ureq::Agent;
 
 use crate::{OsmId, Vendor};
 
-use super::overpass::OsmElement;
+use super::{
+    checkpoint::Checkpoint,
+    gnaf,
+    http::{client, Service},
+    models::LatLon,
+    overpass::OsmElement,
+};
 
 pub fn load(
     vendor: Vendor,
@@ -27,24 +31,27 @@ pub fn load(
         }
     }
 
-    if missing.len() > 0 {
-        let agent = crate::agent();
-
+    if !missing.is_empty() {
+        let local = gnaf::local()?;
         eprintln!(
-            "Fetching GNAF addresses for {} {vendor} stores...",
+            "Finding GNAF addresses for {} {vendor} stores...",
             missing.len()
         );
+        let mut checkpoint = Checkpoint::new(&raw_path, &mut raw);
         for elem in missing {
-            let (x, y) = elem.point.x_y();
-
-            let addr: GNAFAddress = agent
-                .get(&format!("https://api.joel.net.au/gnafr/{x}/{y}"))
-                .call()?
-                .into_json()?;
-            raw.insert(elem.id, addr);
+            let addr = match local {
+                Some(x) => x
+                    .nearest(elem.position)
+                    .context("The G-NAF extract has no addresses")?,
+                None => {
+                    let LatLon { lat, lon } = elem.position;
+                    client().get(Service::Gnafr, &format!("/{lat}/{lon}"))?
+                }
+            };
+            checkpoint.insert(elem.id, addr);
+            checkpoint.tick()?;
         }
-
-        fs::write(raw_path, serde_json::to_string_pretty(&raw)?)?;
+        checkpoint.finish()?;
     }
 
     Ok(raw)
@@ -0,0 +1,430 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::PathBuf,
+    sync::{Mutex, OnceLock},
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Context, Result};
+use clap::Args;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::agent;
+
+/// Attempts at a request before giving up, including the first.
+const MAX_ATTEMPTS: u32 = 6;
+/// Wait before the first retry, doubled for each one after.
+const BACKOFF: Duration = Duration::from_secs(2);
+const MAX_BACKOFF: Duration = Duration::from_secs(300);
+/// Statuses worth retrying, e.g. Overpass answers 429 when its slots are
+/// taken and 504 when overloaded.
+const TRANSIENT_STATUSES: [u16; 5] = [429, 500, 502, 503, 504];
+
+/// An external service the stores pipeline fetches from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Service {
+    Overpass,
+    Nominatim,
+    Gnafr,
+}
+
+impl Service {
+    fn slug(self) -> &'static str {
+        match self {
+            Self::Overpass => "overpass",
+            Self::Nominatim => "nominatim",
+            Self::Gnafr => "gnafr",
+        }
+    }
+
+    /// Minimum time between requests. Nominatim's usage policy allows one
+    /// a second, and Overpass has few slots per client.
+    fn interval(self) -> Duration {
+        match self {
+            Self::Overpass | Self::Nominatim => Duration::from_secs(1),
+            Self::Gnafr => Duration::from_millis(200),
+        }
+    }
+
+    fn default_url(self) -> &'static str {
+        match self {
+            Self::Overpass => "https://overpass-api.de/api",
+            Self::Nominatim => "https://nominatim.openstreetmap.org",
+            Self::Gnafr => "https://api.joel.net.au/gnafr",
+        }
+    }
+}
+
+#[derive(Debug, Default, Args)]
+pub struct HttpArgs {
+    /// Save every response as a fixture in this directory
+    #[arg(long, global = true, conflicts_with = "replay")]
+    record: Option<PathBuf>,
+    /// Answer requests from fixtures in this directory, never the network
+    #[arg(long, global = true)]
+    replay: Option<PathBuf>,
+    /// Base URL of the Overpass API, e.g. a local stand-in server
+    #[arg(long, global = true)]
+    overpass_url: Option<String>,
+    /// Base URL of Nominatim
+    #[arg(long, global = true)]
+    nominatim_url: Option<String>,
+    /// Base URL of the G-NAF reverse geocoder
+    #[arg(long, global = true)]
+    gnafr_url: Option<String>,
+}
+
+#[derive(Debug, Default)]
+enum Mode {
+    #[default]
+    Live,
+    Record(PathBuf),
+    Replay(PathBuf),
+}
+
+#[derive(Debug, Default)]
+pub struct Client {
+    mode: Mode,
+    /// Overrides of the default base URLs
+    urls: BTreeMap<Service, String>,
+    /// When each service was last sent a request
+    last: Mutex<BTreeMap<Service, Instant>>,
+}
+
+static CLIENT: OnceLock<Client> = OnceLock::new();
+
+/// Configures the client for the rest of the run. Only the first call has
+/// any effect.
+pub fn init(args: HttpArgs) {
+    let _ = CLIENT.set(Client::new(args));
+}
+
+pub fn client() -> &'static Client {
+    CLIENT.get_or_init(Client::default)
+}
+
+impl Client {
+    fn new(args: HttpArgs) -> Self {
+        let mode = match (args.record, args.replay) {
+            (_, Some(x)) => Mode::Replay(x),
+            (Some(x), None) => Mode::Record(x),
+            (None, None) => Mode::Live,
+        };
+        let urls = [
+            (Service::Overpass, args.overpass_url),
+            (Service::Nominatim, args.nominatim_url),
+            (Service::Gnafr, args.gnafr_url),
+        ]
+        .into_iter()
+        .filter_map(|(k, v)| Some((k, v?.trim_end_matches('/').to_string())))
+        .collect();
+        Self {
+            mode,
+            urls,
+            ..Self::default()
+        }
+    }
+
+    fn url(&self, service: Service) -> &str {
+        self.urls
+            .get(&service)
+            .map_or(service.default_url(), String::as_str)
+    }
+
+    /// GETs `path`, relative to the service's base URL, as JSON.
+    pub fn get<T: DeserializeOwned>(&self, service: Service, path: &str) -> Result<T> {
+        self.request(service, Request::new("GET", path, &[]))
+    }
+
+    /// POSTs a form to `path`, relative to the service's base URL, with a
+    /// JSON response.
+    pub fn post_form<T: DeserializeOwned>(
+        &self,
+        service: Service,
+        path: &str,
+        form: &[(&str, &str)],
+    ) -> Result<T> {
+        self.request(service, Request::new("POST", path, form))
+    }
+
+    fn request<T: DeserializeOwned>(&self, service: Service, request: Request) -> Result<T> {
+        let response = match &self.mode {
+            Mode::Live => self.send(service, &request)?,
+            Mode::Record(dir) => {
+                let response = self.send(service, &request)?;
+                let path = dir.join(request.fixture_name(service));
+                fs::create_dir_all(dir.join(service.slug()))?;
+                let fixture = Fixture {
+                    request,
+                    response: response.clone(),
+                };
+                fs::write(path, serde_json::to_string_pretty(&fixture)?)?;
+                response
+            }
+            Mode::Replay(dir) => {
+                let path = dir.join(request.fixture_name(service));
+                let contents = fs::read_to_string(&path).with_context(|| {
+                    format!(
+                        "No fixture at {} for {} {}{}, record one with --record",
+                        path.display(),
+                        request.method,
+                        self.url(service),
+                        request.path
+                    )
+                })?;
+                serde_json::from_str::<Fixture>(&contents)?.response
+            }
+        };
+        Ok(serde_json::from_value(response)?)
+    }
+
+    /// Sends a request within the service's rate limit, retrying transient
+    /// failures with exponential backoff or as long as `Retry-After` asks.
+    fn send(&self, service: Service, request: &Request) -> Result<Value> {
+        let url = format!("{}{}", self.url(service), request.path);
+        let form: Vec<(&str, &str)> = request
+            .form
+            .iter()
+            .map(|(k, v)| (k.as_str(), v.as_str()))
+            .collect();
+        let mut attempt = 0;
+        loop {
+            self.wait(service);
+            let response = if request.method == "POST" {
+                agent().post(&url).send_form(&form)
+            } else {
+                agent().get(&url).call()
+            };
+            let (error, retry_after) = match response {
+                Ok(response) => match response.into_json::<Value>() {
+                    Ok(x) => match runtime_error(service, &x) {
+                        Some(remark) => (anyhow!("{remark}"), None),
+                        None => return Ok(x),
+                    },
+                    // the connection dropped mid-body
+                    Err(x) => (x.into(), None),
+                },
+                Err(ureq::Error::Status(code, response)) if TRANSIENT_STATUSES.contains(&code) => {
+                    let retry_after = response.header("Retry-After").map(str::to_string);
+                    (anyhow!("status {code}"), retry_after)
+                }
+                Err(x @ ureq::Error::Transport(_)) => (x.into(), None),
+                Err(x) => return Err(x.into()),
+            };
+
+            attempt += 1;
+            if attempt == MAX_ATTEMPTS {
+                return Err(error.context(format!("Gave up on {url} after {attempt} attempts")));
+            }
+            let delay = backoff(attempt, retry_after.as_deref());
+            eprintln!("{url} failed with {error}, retrying in {delay:?}");
+            thread::sleep(delay);
+        }
+    }
+
+    /// Blocks until the service's interval has passed since its last request.
+    fn wait(&self, service: Service) {
+        let now = Instant::now();
+        let ready = {
+            let mut last = self.last.lock().expect("not poisoned");
+            let ready = last
+                .get(&service)
+                .map_or(now, |x| (*x + service.interval()).max(now));
+            // claimed before sleeping, so other threads queue behind it
+            last.insert(service, ready);
+            ready
+        };
+        thread::sleep(ready - now);
+    }
+}
+
+/// How long to wait before the given retry, from 1, preferring the server's
+/// `Retry-After` in seconds when it sends one.
+fn backoff(attempt: u32, retry_after: Option<&str>) -> Duration {
+    let delay = match retry_after.and_then(|x| x.trim().parse().ok()) {
+        Some(secs) => Duration::from_secs(secs),
+        None => BACKOFF.saturating_mul(1 << (attempt - 1).min(16)),
+    };
+    delay.min(MAX_BACKOFF)
+}
+
+/// Overpass reports timeouts and running out of memory in a remark on an
+/// otherwise successful response.
+fn runtime_error(service: Service, response: &Value) -> Option<&str> {
+    if service != Service::Overpass {
+        return None;
+    }
+    response["remark"]
+        .as_str()
+        .filter(|x| x.contains("runtime error"))
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct Request {
+    method: String,
+    path: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    form: Vec<(String, String)>,
+}
+
+impl Request {
+    fn new(method: &str, path: &str, form: &[(&str, &str)]) -> Self {
+        Self {
+            method: method.to_string(),
+            path: path.to_string(),
+            form: form
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+        }
+    }
+
+    /// Named by a hash of the request, leaving out the base URL so fixtures
+    /// replay against any server.
+    fn fixture_name(&self, service: Service) -> PathBuf {
+        // FNV-1a, as it's stable across builds unlike std's hasher
+        let mut hash: u64 = 0xcbf29ce484222325;
+        let mut add = |bytes: &[u8]| {
+            for x in bytes {
+                hash ^= *x as u64;
+                hash = hash.wrapping_mul(0x100000001b3);
+            }
+        };
+        add(self.method.as_bytes());
+        add(b" ");
+        add(self.path.as_bytes());
+        for (k, v) in &self.form {
+            add(b"\n");
+            add(k.as_bytes());
+            add(b"=");
+            add(v.as_bytes());
+        }
+        PathBuf::from(format!("{}/{hash:016x}.json", service.slug()))
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct Fixture {
+    request: Request,
+    response: Value,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{Read, Write},
+        net::TcpListener,
+    };
+
+    use super::*;
+
+    /// Serves one response per request, as a status line and extra headers
+    /// then the body, returning the base URL.
+    fn stand_in(responses: Vec<(&'static str, &'static str)>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            for (head, body) in responses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut buf = [0; 4096];
+                let mut request: Vec<u8> = Vec::new();
+                // the body may follow, but is never needed
+                while !request.windows(4).any(|x| x == b"\r\n\r\n") {
+                    let n = stream.read(&mut buf).unwrap();
+                    request.extend(&buf[..n]);
+                }
+                write!(
+                    stream,
+                    "{head}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                    body.len()
+                )
+                .unwrap();
+            }
+        });
+        format!("http://{addr}")
+    }
+
+    #[test]
+    fn record_then_replay() {
+        let dir = std::env::temp_dir().join(format!("stores-http-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let body = r#"{"locality":"SYDNEY","postcode":"2000"}"#;
+
+        let record = Client {
+            mode: Mode::Record(dir.clone()),
+            urls: BTreeMap::from([(Service::Gnafr, stand_in(vec![("HTTP/1.1 200 OK", body)]))]),
+            ..Client::default()
+        };
+        let recorded: Value = record.get(Service::Gnafr, "/-33.8/151.2").unwrap();
+        assert_eq!(recorded["postcode"], "2000");
+
+        // the stand-in has stopped, so this can only come from the fixture
+        let replay = Client {
+            mode: Mode::Replay(dir.clone()),
+            ..Client::default()
+        };
+        let replayed: Value = replay.get(Service::Gnafr, "/-33.8/151.2").unwrap();
+        assert_eq!(recorded, replayed);
+        let missing = replay.get::<Value>(Service::Gnafr, "/-33.9/151.2");
+        assert!(missing.unwrap_err().to_string().contains("No fixture"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn fixture_names() {
+        let get = Request::new("GET", "/lookup?osm_ids=n1", &[]);
+        let post = Request::new("POST", "/interpreter", &[("data", "out;")]);
+        let other = Request::new("POST", "/interpreter", &[("data", "out tags;")]);
+        assert!(get
+            .fixture_name(Service::Nominatim)
+            .starts_with("nominatim"));
+        assert_ne!(
+            post.fixture_name(Service::Overpass),
+            other.fixture_name(Service::Overpass)
+        );
+    }
+
+    #[test]
+    fn retries() {
+        let url = stand_in(vec![
+            (
+                "HTTP/1.1 200 OK",
+                r#"{"remark": "runtime error: Query timed out"}"#,
+            ),
+            ("HTTP/1.1 429 Too Many Requests\r\nRetry-After: 0", "{}"),
+            ("HTTP/1.1 200 OK", r#"{"elements": []}"#),
+            ("HTTP/1.1 404 Not Found", "{}"),
+        ]);
+        let client = Client {
+            urls: BTreeMap::from([(Service::Overpass, url)]),
+            ..Client::default()
+        };
+        let start = Instant::now();
+        let x: Value = client
+            .post_form(Service::Overpass, "/interpreter", &[("data", "out;")])
+            .unwrap();
+        assert_eq!(x["elements"], serde_json::json!([]));
+        // a backoff of 2s, then the 429 asked for no wait
+        assert!(start.elapsed() >= BACKOFF);
+        // other errors aren't retried
+        let x = client.post_form::<Value>(Service::Overpass, "/interpreter", &[]);
+        assert!(x.unwrap_err().to_string().contains("404"));
+    }
+
+    #[test]
+    fn backoff_delays() {
+        assert_eq!(backoff(1, None), Duration::from_secs(2));
+        assert_eq!(backoff(3, None), Duration::from_secs(8));
+        assert_eq!(backoff(20, None), MAX_BACKOFF);
+        assert_eq!(backoff(3, Some("30")), Duration::from_secs(30));
+        // HTTP dates aren't supported
+        assert_eq!(
+            backoff(1, Some("Wed, 21 Oct 2015 07:28:00 GMT")),
+            Duration::from_secs(2)
+        );
+    }
+}
@@ -0,0 +1,131 @@
+use std::{collections::BTreeMap, fs, path::Path};
+
+use anyhow::{Context, Result};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{OsmId, Vendor};
+
+use super::{
+    models::{LatLon, StoreId},
+    overpass::OsmElement,
+    Store, OUTPUT_PATH,
+};
+
+/// Rewrites cached files written with `geo::Point` positions, which stored
+/// the latitude in x and the longitude in y, to named lat/lon fields.
+/// Files already migrated are left alone.
+pub fn main() -> Result<()> {
+    for vendor in Vendor::all() {
+        let slug = vendor.slug();
+        migrate::<BTreeMap<OsmId, LegacyElement>, BTreeMap<OsmId, OsmElement>>(
+            &format!("data/stores/osm-elems-{slug}.json"),
+            |x| x.into_iter().map(|(k, v)| (k, v.into())).collect(),
+            true,
+        )?;
+        migrate::<BTreeMap<OsmId, Vec<LegacyElement>>, BTreeMap<OsmId, Vec<OsmElement>>>(
+            &format!("data/stores/osm-areas-{slug}.json"),
+            |x| {
+                x.into_iter()
+                    .map(|(k, v)| (k, v.into_iter().map(Into::into).collect()))
+                    .collect()
+            },
+            false,
+        )?;
+    }
+    migrate::<Vec<LegacyStore>, Vec<Store>>(
+        OUTPUT_PATH,
+        |x| x.into_iter().map(Into::into).collect(),
+        false,
+    )?;
+
+    Ok(())
+}
+
+fn migrate<Old, New>(path: &str, convert: impl FnOnce(Old) -> New, newline: bool) -> Result<()>
+where
+    Old: DeserializeOwned,
+    New: DeserializeOwned + Serialize,
+{
+    if !Path::new(path).exists() {
+        return Ok(());
+    }
+    let contents = fs::read_to_string(path)?;
+    if serde_json::from_str::<New>(&contents).is_ok() {
+        eprintln!("{path} is already migrated");
+        return Ok(());
+    }
+
+    let old: Old =
+        serde_json::from_str(&contents).with_context(|| format!("Failed to read {path}"))?;
+    let mut contents = serde_json::to_string_pretty(&convert(old))?;
+    if newline {
+        contents.push('\n');
+    }
+    fs::write(path, contents)?;
+    eprintln!("Migrated {path}");
+
+    Ok(())
+}
+
+#[derive(Deserialize)]
+struct LegacyElement {
+    id: OsmId,
+    x: f64,
+    y: f64,
+    tags: BTreeMap<String, String>,
+}
+
+impl From<LegacyElement> for OsmElement {
+    fn from(x: LegacyElement) -> Self {
+        Self {
+            id: x.id,
+            position: LatLon::new(x.x, x.y),
+            tags: x.tags,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct LegacyStore {
+    #[serde(flatten)]
+    id: StoreId,
+    name: String,
+    desc: String,
+    osm: OsmId,
+    x: f64,
+    y: f64,
+}
+
+impl From<LegacyStore> for Store {
+    fn from(x: LegacyStore) -> Self {
+        Self {
+            id: x.id,
+            name: x.name,
+            desc: x.desc,
+            osm: x.osm,
+            // filled in by the next `stores` build
+            centre: None,
+            state: String::new(),
+            position: LatLon::new(x.x, x.y),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn legacy_element() {
+        let old: LegacyElement =
+            serde_json::from_str(r#"{"id": "n17027753", "x": -33.8, "y": 151.2, "tags": {}}"#)
+                .unwrap();
+        let new = OsmElement::from(old);
+        assert_eq!(new.position, LatLon::new(-33.8, 151.2));
+        assert_eq!(new.position.point().x(), 151.2);
+        assert_eq!(
+            serde_json::to_string(&new).unwrap(),
+            r#"{"id":"n17027753","lat":-33.8,"lon":151.2,"tags":{}}"#
+        );
+    }
+}
@@ -1,27 +1,72 @@
 use std::{
-    collections::{BTreeMap, BTreeSet, HashSet},
     fmt::Write,
     fs,
+    path::{Path, PathBuf},
 };
 
-use anyhow::{bail, Result};
-use geo::Point;
-use itertools::Itertools;
-use serde::Serialize;
+use anyhow::Result;
+use clap::Subcommand;
+use serde::{Deserialize, Serialize};
 
 use crate::{utils::title_case, OsmId, Vendor};
 
-use self::models::StoreId;
+use self::http::HttpArgs;
+use self::models::{LatLon, StoreId};
 
+mod assignment;
+mod changelog;
+mod checkpoint;
+mod export;
+mod gnaf;
 mod gnaf_addrs;
+mod http;
+mod migrate;
 mod models;
 mod osm_addrs;
 mod osm_areas;
 mod osm_elems;
 mod osm_ids;
 mod overpass;
+mod overrides;
+mod refresh;
 
-pub fn main() -> Result<()> {
+const OUTPUT_PATH: &str = "data/stores/output.json";
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Rewrite cached files from x/y points to lat/lon, a one-off
+    MigrateCoordinates,
+    /// Re-query OSM, report changes since the cache and fetch only what
+    /// changed before building
+    Refresh,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct Args {
+    #[command(flatten)]
+    http: HttpArgs,
+    /// Reverse geocode from this G-NAF address extract, pipe separated if
+    /// `.psv` or else comma separated, instead of the remote API
+    #[arg(long, global = true)]
+    gnaf: Option<PathBuf>,
+}
+
+pub fn main(command: Option<Command>, args: Args) -> Result<()> {
+    http::init(args.http);
+    gnaf::init(args.gnaf);
+    match command {
+        None => build(),
+        Some(Command::MigrateCoordinates) => migrate::main(),
+        Some(Command::Refresh) => refresh::main(),
+    }
+}
+
+fn build() -> Result<()> {
+    let previous: Option<Vec<Store>> = if Path::new(OUTPUT_PATH).exists() {
+        Some(serde_json::from_str(&fs::read_to_string(OUTPUT_PATH)?)?)
+    } else {
+        None
+    };
     let mut output = Vec::new();
 
     for vendor in Vendor::all() {
@@ -32,9 +77,9 @@ pub fn main() -> Result<()> {
         let osm_areas = osm_areas::load(vendor, &osm_elems, &osm_ids)?;
 
         for (store, osm) in &osm_ids {
-            let elem = osm_elems.get(&osm).unwrap();
-            let gnaf_addr = gnaf_addrs.get(&osm).unwrap();
-            let osm_addr = osm_addrs.get(&osm).unwrap();
+            let elem = osm_elems.get(osm).unwrap();
+            let gnaf_addr = gnaf_addrs.get(osm).unwrap();
+            let osm_addr = osm_addrs.get(osm).unwrap();
 
             let mut name = elem
                 .tags
@@ -54,13 +99,13 @@ pub fn main() -> Result<()> {
                 name += &locality;
             }
 
-            if let Some(x) = osm_areas.get(&osm) {
+            if let Some(x) = osm_areas.get(osm) {
                 write!(desc, "{x}, ")?;
             }
             if osm_addr.is_empty() {
                 desc += &locality;
             } else {
-                desc += &osm_addr;
+                desc += osm_addr;
             }
             write!(desc, " {} {}", gnaf_addr.state, gnaf_addr.postcode)?;
 
@@ -69,26 +114,35 @@ pub fn main() -> Result<()> {
                 name,
                 desc,
                 osm: *osm,
-                point: elem.point.clone(),
+                centre: osm_areas.get(osm).cloned(),
+                state: gnaf_addr.state.clone(),
+                position: elem.position,
             })
         }
     }
 
-    fs::write(
-        "data/stores/output.json",
-        serde_json::to_string_pretty(&output)?,
-    )?;
+    fs::write(OUTPUT_PATH, serde_json::to_string_pretty(&output)?)?;
+    fs::write(export::GEOJSON_PATH, export::geojson(&output)?)?;
+    fs::write(export::FLATGEOBUF_PATH, export::flatgeobuf(&output))?;
+    if let Some(previous) = previous {
+        changelog::main(&previous, &output)?;
+    }
 
     Ok(())
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 struct Store {
     #[serde(flatten)]
     id: StoreId,
     name: String,
     desc: String,
     osm: OsmId,
+    /// The shopping centre the store is in
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    centre: Option<String>,
+    #[serde(default)]
+    state: String,
     #[serde(flatten)]
-    point: Point,
+    position: LatLon,
 }
@@ -1,5 +1,6 @@
 use std::fmt;
 
+use geo::{HaversineDistance, Point};
 use serde::{Deserialize, Serialize};
 
 use crate::Vendor;
@@ -20,6 +21,13 @@ pub enum StoreId {
 }
 
 impl StoreId {
+    pub fn new(vendor: Vendor, id: u32) -> Self {
+        match vendor {
+            Vendor::Coles => Self::Coles(id),
+            Vendor::Woolworths => Self::Woolworths(id),
+        }
+    }
+
     pub fn vendor(&self) -> Vendor {
         match self {
             Self::Coles(_) => Vendor::Coles,
@@ -42,6 +50,30 @@ impl StoreId {
     }
 }
 
+/// A WGS84 position with named fields, as `geo::Point` leaves it to the
+/// caller to remember that x is the longitude.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LatLon {
+    pub lat: f64,
+    pub lon: f64,
+}
+
+impl LatLon {
+    pub fn new(lat: f64, lon: f64) -> Self {
+        Self { lat, lon }
+    }
+
+    /// The position as a `geo::Point`, with x the longitude and y the latitude.
+    pub fn point(&self) -> Point {
+        Point::new(self.lon, self.lat)
+    }
+
+    /// Great-circle distance in metres.
+    pub fn distance(&self, other: &Self) -> f64 {
+        self.point().haversine_distance(&other.point())
+    }
+}
+
 impl Vendor {
     pub fn parse_store_id(&self, id: &str) -> Option<StoreId> {
         Some(match self {
@@ -102,4 +134,13 @@ mod tests {
             Some(StoreId::Woolworths(1234))
         );
     }
+
+    #[test]
+    fn distance() {
+        let sydney = LatLon::new(-33.8688, 151.2093);
+        let melbourne = LatLon::new(-37.8136, 144.9631);
+        assert_eq!(sydney.point().x(), 151.2093);
+        // about 714km, swapping latitude and longitude gives about 1000km
+        assert!((sydney.distance(&melbourne) - 714_000.0).abs() < 5_000.0);
+    }
 }
@@ -1,22 +1,20 @@
-use std::{
-    collections::BTreeMap,
-    fmt,
-    fs::{read_to_string, write},
-    path::PathBuf,
-};
+use std::{collections::BTreeMap, fs::read_to_string, path::PathBuf};
 
-use anyhow::{bail, Result};
+use anyhow::Result;
 use indicatif::ProgressBar;
 use itertools::Itertools;
 use serde::Deserialize;
-use ureq::Agent;
 
 use crate::{
     utils::{progress_style, EMAIL},
     OsmId, Vendor,
 };
 
-use super::models::StoreId;
+use super::{
+    checkpoint::Checkpoint,
+    http::{client, Service},
+    models::StoreId,
+};
 
 pub fn load(vendor: Vendor, osm_ids: &BTreeMap<StoreId, OsmId>) -> Result<BTreeMap<OsmId, String>> {
     let raw_path = PathBuf::from(format!("data/stores/osm-addrs-{}.json", vendor.slug()));
@@ -33,31 +31,27 @@ pub fn load(vendor: Vendor, osm_ids: &BTreeMap<StoreId, OsmId>) -> Result<BTreeM
         }
     }
 
-    if missing.len() > 0 {
-        let agent = Agent::new();
-
+    if !missing.is_empty() {
         eprintln!(
             "Fetching OSM addresses for {} {vendor} stores...",
             missing.len()
         );
         let pb = ProgressBar::new(missing.len() as u64).with_style(progress_style());
+        let mut checkpoint = Checkpoint::new(&raw_path, &mut raw);
         for chunk in missing.chunks(50) {
             let chunk = chunk.iter().join(",");
 
-            let response: Vec<Geocoding> = agent
-                .get(&format!(
-                    "https://nominatim.openstreetmap.org/lookup?osm_ids={chunk}&format=json&email={EMAIL}"
-                ))
-                .call()?
-                .into_json()?;
+            let response: Vec<Geocoding> = client().get(
+                Service::Nominatim,
+                &format!("/lookup?osm_ids={chunk}&format=json&email={EMAIL}"),
+            )?;
             pb.inc(response.len() as u64);
             for x in response {
-                raw.insert(x.osm_id(), x.address);
+                checkpoint.insert(x.osm_id(), x.address);
             }
+            checkpoint.tick()?;
         }
-
-        // write now to keep raw data
-        write(raw_path, serde_json::to_string_pretty(&raw)?)?;
+        checkpoint.finish()?;
     }
 
     let mut output = BTreeMap::new();
@@ -123,11 +117,6 @@ enum OsmType {
     Relation,
 }
 
-#[derive(Deserialize)]
-pub struct OSMAddress {
-    pub road: Option<String>,
-}
-
 // #[derive(Debug)]
 // pub enum State {
 //     NSW,
@@ -10,7 +10,10 @@ use indicatif::ProgressIterator;
 
 use crate::{stores::overpass, utils::progress_style, OsmId, Vendor};
 
-use super::models::StoreId;
+use super::{
+    checkpoint::Checkpoint,
+    models::{LatLon, StoreId},
+};
 
 pub fn load(
     vendor: Vendor,
@@ -26,24 +29,25 @@ pub fn load(
 
     let mut missing = Vec::new();
     for (store, osm) in osm_ids {
-        if !raw.contains_key(&osm) {
+        if !raw.contains_key(osm) {
             missing.push((store, osm));
         }
     }
 
-    if missing.len() > 0 {
+    if !missing.is_empty() {
         eprintln!("Fetching areas for {} {vendor} stores...", missing.len());
+        let mut checkpoint = Checkpoint::new(&raw_path, &mut raw);
         for (_, osm) in missing.iter().progress_with_style(progress_style()) {
             let elem = osm_elems
                 .get(osm)
                 .expect("stores should only reference known elements");
 
-            let (x, y) = elem.point.x_y();
-            raw.insert(
+            let LatLon { lat, lon } = elem.position;
+            checkpoint.insert(
                 **osm,
                 overpass::query(&format!(
                     r#"
-                        is_in({x},{y}) ->.b;
+                        is_in({lat},{lon}) ->.b;
                         (
                         	area(pivot.b)["building"];
                         	relation(pivot.b)["building"];
@@ -54,9 +58,9 @@ pub fn load(
                     "#
                 ))?,
             );
+            checkpoint.tick()?;
         }
-
-        write(&raw_path, serde_json::to_string_pretty(&raw)?)?;
+        checkpoint.finish()?;
     }
 
     let mut output = BTreeMap::new();
@@ -65,10 +69,10 @@ pub fn load(
         let raw = raw.get(osm).unwrap();
         let parents: Vec<_> = raw
             .iter()
-            .filter(|x| !x.tags.get("shop").is_some_and(|x| x == "supermarket"))
+            .filter(|x| x.tags.get("shop").is_none_or(|x| x != "supermarket"))
             // .filter(|x| x.tags.get("shop").is_some_and(|x| x == "mall"))
             .collect();
-        if parents.len() == 0 {
+        if parents.is_empty() {
             writeln!(todo, "- {store:?} {osm:?} has no parents")?;
         }
         // if parents.len() == 2 {
@@ -6,41 +6,60 @@ use crate::{OsmId, Vendor};
 
 use super::overpass;
 
+fn path(vendor: Vendor) -> PathBuf {
+    PathBuf::from(format!("data/stores/osm-elems-{}.json", vendor.slug()))
+}
+
 pub fn load(vendor: Vendor) -> Result<BTreeMap<OsmId, overpass::OsmElement>> {
-    let path = PathBuf::from(format!("data/stores/osm-elems-{}.json", vendor.slug()));
-    if path.exists() {
-        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
-    } else {
-        eprintln!("Fetching OSM elements for {vendor}...");
-        let elems = overpass::query(match vendor {
-            Vendor::Coles => {
-                r#"
-                    (
-                        nwr["brand:wikidata"="Q1108172"];
-                        nwr["brand:wikidata"="Q104850818"];
-                    );
-                    out tags center;
-                "#
-            }
-            Vendor::Woolworths => {
-                r#"
-                    (
-                        nwr["brand:wikidata"="Q3249145"];
-                        nwr["brand:wikidata"="Q111772555"]["name"!="Woolworths MetroGo"];
-                    );
-                    out tags center;
-                "#
-            }
-        })?;
-
-        let mut map = BTreeMap::new();
-        for x in elems {
-            map.insert(x.id, x);
+    if let Some(x) = cached(vendor)? {
+        return Ok(x);
+    }
+    let map = fetch(vendor)?;
+    write(vendor, &map)?;
+    Ok(map)
+}
+
+pub fn cached(vendor: Vendor) -> Result<Option<BTreeMap<OsmId, overpass::OsmElement>>> {
+    let path = path(vendor);
+    if !path.exists() {
+        return Ok(None);
+    }
+    Ok(Some(serde_json::from_str(&fs::read_to_string(path)?)?))
+}
+
+pub fn fetch(vendor: Vendor) -> Result<BTreeMap<OsmId, overpass::OsmElement>> {
+    eprintln!("Fetching OSM elements for {vendor}...");
+    let elems = overpass::query(match vendor {
+        Vendor::Coles => {
+            r#"
+                (
+                    nwr["brand:wikidata"="Q1108172"];
+                    nwr["brand:wikidata"="Q104850818"];
+                );
+                out tags center;
+            "#
+        }
+        Vendor::Woolworths => {
+            r#"
+                (
+                    nwr["brand:wikidata"="Q3249145"];
+                    nwr["brand:wikidata"="Q111772555"]["name"!="Woolworths MetroGo"];
+                );
+                out tags center;
+            "#
         }
+    })?;
 
-        let mut contents = serde_json::to_string_pretty(&map)?;
-        contents.push('\n');
-        fs::write(path, &contents)?;
-        Ok(map)
+    let mut map = BTreeMap::new();
+    for x in elems {
+        map.insert(x.id, x);
     }
+    Ok(map)
+}
+
+pub fn write(vendor: Vendor, elems: &BTreeMap<OsmId, overpass::OsmElement>) -> Result<()> {
+    let mut contents = serde_json::to_string_pretty(elems)?;
+    contents.push('\n');
+    fs::write(path(vendor), &contents)?;
+    Ok(())
 }
@@ -1,37 +1,85 @@
 use std::{
     collections::{BTreeMap, BTreeSet},
-    fmt::Write,
+    fmt::{self, Write},
     fs::{read_to_string, write},
-    path::PathBuf,
+    path::{Path, PathBuf},
 };
 
 use anyhow::{bail, Context, Result};
-use geo::{HaversineDistance, Point};
 use serde::{Deserialize, Serialize};
 
 use crate::{OsmId, Vendor};
 
-use super::{models::StoreId, overpass::OsmElement};
+use super::{
+    assignment::{self, Assignment, Candidate},
+    models::{LatLon, StoreId},
+    overpass::OsmElement,
+    overrides::{self, Overrides},
+};
 
 // metres
 const MATCH_RADIUS: f64 = 500.0;
 const WARNING_RADIUS: f64 = 400.0;
 
+// smaller formats first, so their longer prefix wins
+const BRANDS: [(Vendor, &str, &str); 4] = [
+    (Vendor::Coles, "coles local", "Q104850818"),
+    (Vendor::Coles, "coles", "Q1108172"),
+    (Vendor::Woolworths, "woolworths metro", "Q111772555"),
+    (Vendor::Woolworths, "woolworths", "Q3249145"),
+];
+
+fn path(vendor: Vendor) -> PathBuf {
+    PathBuf::from(format!("data/stores/osm-ids-{}.jsonl", vendor.slug()))
+}
+
 pub fn load(
     vendor: Vendor,
     osm_elems: &BTreeMap<OsmId, OsmElement>,
 ) -> Result<BTreeMap<StoreId, OsmId>> {
-    let output_path = PathBuf::from(format!("data/stores/osm-ids-{}.jsonl", vendor.slug()));
-    if output_path.exists() {
-        let mut output = BTreeMap::new();
-        for line in read_to_string(output_path)?.lines() {
-            let dump: DataDump = serde_json::from_str(line)?;
-            output.insert(dump.id, dump.osm);
-        }
-        return Ok(output);
+    let path = path(vendor);
+    if path.exists() && !overrides::modified_since(vendor, &path)? {
+        return cached(&path);
     }
+    conflate(vendor, osm_elems, BTreeMap::new())
+}
 
+/// Matches stores again, keeping cached matches to elements that aren't
+/// `stale`.
+pub fn rematch(
+    vendor: Vendor,
+    osm_elems: &BTreeMap<OsmId, OsmElement>,
+    stale: &BTreeSet<OsmId>,
+) -> Result<()> {
+    let path = path(vendor);
+    let mut kept = if path.exists() {
+        cached(&path)?
+    } else {
+        BTreeMap::new()
+    };
+    kept.retain(|_, osm| !stale.contains(osm) && osm_elems.contains_key(osm));
+    conflate(vendor, osm_elems, kept)?;
+    Ok(())
+}
+
+fn cached(path: &Path) -> Result<BTreeMap<StoreId, OsmId>> {
     let mut output = BTreeMap::new();
+    for line in read_to_string(path)?.lines() {
+        let dump: DataDump = serde_json::from_str(line)?;
+        output.insert(dump.id, dump.osm);
+    }
+    Ok(output)
+}
+
+fn conflate(
+    vendor: Vendor,
+    osm_elems: &BTreeMap<OsmId, OsmElement>,
+    kept: BTreeMap<StoreId, OsmId>,
+) -> Result<BTreeMap<StoreId, OsmId>> {
+    let output_path = path(vendor);
+    let overrides = Overrides::load(vendor)?;
+    let ignored = overrides.ignored(vendor);
+    let never = overrides.never(vendor);
     let mut todo = String::new();
 
     let mut raw = Vec::new();
@@ -39,16 +87,17 @@ pub fn load(
         .context("failed to read internal data source")?
         .lines()
     {
-        let x: RawStore = serde_json::from_str(&x)?;
-        raw.push(x);
+        let x: RawStore = serde_json::from_str(x)?;
+        if !ignored.contains(&StoreId::new(vendor, x.id)) {
+            raw.push(x);
+        }
     }
 
-    let mut forced = BTreeSet::new();
+    let mut website = BTreeMap::new();
     for x in osm_elems.values() {
         if let Some(url) = x.tags.get("website") {
             if let Some(id) = vendor.parse_store_link(url) {
-                forced.insert(x.id.clone());
-                if let Some(conflict) = output.insert(id, x.id) {
+                if let Some(conflict) = website.insert(id, x.id) {
                     bail!(
                         "Two elements reference store {id:?}: {:?} {:?}",
                         x.id,
@@ -59,102 +108,98 @@ pub fn load(
         }
     }
 
-    let mut nearby: BTreeMap<StoreId, Vec<(f64, &OsmElement)>> = BTreeMap::new();
-    let mut nearest: BTreeMap<OsmId, f64> = BTreeMap::new();
+    // overrides are applied before automatic matching, so win over it
+    let (mut output, conflicts) = overrides.apply(vendor, &website, osm_elems);
+    for x in conflicts {
+        writeln!(todo, "- {x}")?;
+    }
+    let from_url = output
+        .iter()
+        .filter(|(k, v)| website.get(k) == Some(v))
+        .count();
+    let forced = output.len() - from_url;
+    let reused = keep(&mut output, kept, &ignored, &never);
+    let fixed = output.len();
+    let taken: BTreeSet<OsmId> = output.values().copied().collect();
+
+    let mut candidates = Vec::new();
+    let mut missing = Vec::new();
     for store in &raw {
-        let id = match vendor {
-            Vendor::Coles => StoreId::Coles(store.id),
-            Vendor::Woolworths => StoreId::Woolworths(store.id),
-        };
+        let id = StoreId::new(vendor, store.id);
         if output.contains_key(&id) {
-            // matched based on url
+            // matched based on url or overrides
             continue;
         }
 
         // find osm objects nearby
-        let mut this_nearby = Vec::new();
+        let before = candidates.len();
         for x in osm_elems.values() {
-            if forced.contains(&x.id) {
-                // matched based on url
+            if taken.contains(&x.id) || never.contains(&(id, x.id)) {
                 continue;
             }
 
-            let distance = store.point.haversine_distance(&x.point);
+            let distance = store.position().distance(&x.position);
             if distance < MATCH_RADIUS {
-                if let Some(x) = nearest.get_mut(&x.id) {
-                    if *x > distance {
-                        *x = distance;
-                    }
-                } else {
-                    nearest.insert(x.id.clone(), distance);
-                }
-
-                this_nearby.push((distance, x));
+                let score = Score::new(vendor, store, x, distance);
+                candidates.push(Candidate {
+                    store: id,
+                    osm: x.id,
+                    distance,
+                    cost: 1.0 - score.total,
+                    detail: score,
+                });
             }
         }
-        this_nearby.sort_by(|(a, _), (b, _)| a.total_cmp(b));
-        this_nearby.reverse(); // can use .pop() to get nearest
-        nearby.insert(id, this_nearby);
+        if candidates.len() == before {
+            missing.push(id);
+        }
     }
 
-    let missing = nearby
-        .iter()
-        .filter(|(_, x)| x.len() == 0)
-        .collect::<Vec<_>>();
-
-    if missing.len() > 0 {
+    missing.sort();
+    if !missing.is_empty() {
         writeln!(todo, "- didn't match with osm:")?;
-        for (k, _) in missing {
-            writeln!(todo, "  - {k:?}")?;
+        for x in &missing {
+            writeln!(todo, "  - {x:?}")?;
         }
     }
 
-    let mut keep_going: bool = true;
-    let mut pass = 0;
-    while keep_going {
-        keep_going = false;
-        pass += 1;
-
-        for (store, this_nearby) in nearby.iter_mut() {
-            if let Some((d, next)) = this_nearby.pop() {
-                keep_going = true;
-
-                if *nearest.get(&next.id).unwrap() == d {
-                    if let Some(x) = output.get(store) {
-                        writeln!(
-                            todo,
-                            "- {store:?} is conflicted with {:?} and {:?}",
-                            x, next.id
-                        )?;
-                    } else {
-                        if pass > 1 {
-                            writeln!(
-                                todo,
-                                "- {:?} is #{pass} closest to {store:?} ({d:.00}m)",
-                                next.id
-                            )?;
-                        } else if d > WARNING_RADIUS {
-                            writeln!(
-                                todo,
-                                "- {store:?}: {:?} is {d:.00}m away from raw location",
-                                next.id
-                            )?;
-                        }
-                        output.insert(store.clone(), next.id);
-                    }
-                }
-            }
+    let before = assignment::nearest_passes(&candidates);
+    let after = assignment::optimal(&candidates);
+
+    let unassigned: BTreeSet<StoreId> = candidates
+        .iter()
+        .map(|x| x.store)
+        .filter(|x| !after.contains_key(x))
+        .collect();
+    if !unassigned.is_empty() {
+        writeln!(todo, "- nearby osm all matched with other stores:")?;
+        for x in unassigned {
+            writeln!(todo, "  - {x:?}")?;
+        }
+    }
+    let mut scores = String::new();
+    for (store, x) in &after {
+        let (osm, d) = (x.osm, x.distance);
+        if d > WARNING_RADIUS {
+            writeln!(
+                todo,
+                "- {store:?}: {osm:?} is {d:.00}m away from raw location"
+            )?;
+        }
+        if x.detail.brand == 0.0 {
+            writeln!(todo, "- {store:?}: {osm:?} is a different format")?;
         }
+        writeln!(scores, "- {store:?} {osm:?} {}", x.detail)?;
+        output.insert(*store, osm);
     }
 
-    let mut existing = BTreeSet::from_iter(forced.iter());
-    existing.extend(output.iter().map(|(_, v)| v));
+    let existing: BTreeSet<&OsmId> = output.values().collect();
     let missing = osm_elems
         .iter()
         .map(|x| x.1.id)
         .filter(|x| !existing.contains(x))
         .collect::<Vec<_>>();
-    if missing.len() > 0 {
+    if !missing.is_empty() {
         writeln!(todo, "- didn't match with raw:")?;
         for x in missing {
             writeln!(todo, "  - {x:?}")?;
@@ -164,19 +209,44 @@ pub fn load(
     let mut md = String::new();
     writeln!(md, "## Statistics\n")?;
     writeln!(md, "- {} raw, {} osm", raw.len(), osm_elems.len())?;
-    writeln!(md, "- {} sourced from url", forced.len())?;
-    writeln!(md, "- {} matched on distance", output.len() - forced.len())?;
+    writeln!(md, "- {} sourced from url", from_url)?;
+    writeln!(md, "- {} forced by overrides", forced)?;
+    writeln!(md, "- {} kept from before a refresh", reused)?;
+    writeln!(md, "- {} ignored by overrides", ignored.len())?;
+    writeln!(
+        md,
+        "- {} matched on distance, {} with nearest passes",
+        after.len(),
+        before.len()
+    )?;
+    writeln!(
+        md,
+        "- {:.0}m mean distance, {:.0}m with nearest passes",
+        mean_distance(&after),
+        mean_distance(&before)
+    )?;
+    let conflated = |x: &Assignment<Score>| (fixed + x.len()) as f64 / raw.len() as f64 * 100.0;
     writeln!(
         md,
-        "- {:.01}% conflated",
-        output.len() as f64 / raw.len() as f64 * 100.0
+        "- {:.01}% conflated, {:.01}% with nearest passes",
+        conflated(&after),
+        conflated(&before)
     )?;
     writeln!(md)?;
     if !todo.is_empty() {
         writeln!(md, "## Todo\n")?;
         writeln!(md, "{todo}")?;
     }
+    if !scores.is_empty() {
+        writeln!(md, "## Scores\n")?;
+        writeln!(md, "{scores}")?;
+    }
     write(output_path.with_extension("md"), md)?;
+    eprintln!(
+        "Conflated {:.01}% of {vendor} stores, {:.01}% with nearest passes",
+        conflated(&after),
+        conflated(&before)
+    );
 
     let mut contents = String::new();
     for (id, osm) in &output {
@@ -189,11 +259,124 @@ pub fn load(
     Ok(output)
 }
 
+fn keep(
+    output: &mut BTreeMap<StoreId, OsmId>,
+    kept: BTreeMap<StoreId, OsmId>,
+    ignored: &BTreeSet<StoreId>,
+    never: &BTreeSet<(StoreId, OsmId)>,
+) -> usize {
+    let mut taken: BTreeSet<OsmId> = output.values().copied().collect();
+    let mut count = 0;
+    for (store, osm) in kept {
+        if output.contains_key(&store)
+            || taken.contains(&osm)
+            || ignored.contains(&store)
+            || never.contains(&(store, osm))
+        {
+            continue;
+        }
+        output.insert(store, osm);
+        taken.insert(osm);
+        count += 1;
+    }
+    count
+}
+
+fn mean_distance(x: &Assignment<Score>) -> f64 {
+    if x.is_empty() {
+        return 0.0;
+    }
+    x.values().map(|x| x.distance).sum::<f64>() / x.len() as f64
+}
+
+// each part is between 0 and 1, or 0.5 if it can't be compared
+#[derive(Debug, Clone, Copy)]
+struct Score {
+    distance: f64,
+    metres: f64,
+    brand: f64,
+    name: f64,
+    total: f64,
+}
+
+impl Score {
+    fn new(vendor: Vendor, store: &RawStore, elem: &OsmElement, metres: f64) -> Self {
+        let distance = 1.0 - metres / MATCH_RADIUS;
+        let expected = store
+            .name
+            .as_deref()
+            .and_then(|x| expected_brand(vendor, x));
+        let brand = match (expected, elem.tags.get("brand:wikidata")) {
+            (Some(a), Some(b)) if a == b => 1.0,
+            (Some(_), Some(_)) => 0.0,
+            _ => 0.5,
+        };
+        let osm_name = elem.tags.get("branch").or_else(|| elem.tags.get("name"));
+        let name = match (
+            store.name.as_deref().map(branch),
+            osm_name.map(|x| branch(x)),
+        ) {
+            (Some(a), Some(b)) if !a.is_empty() && !b.is_empty() => strsim::jaro_winkler(&a, &b),
+            _ => 0.5,
+        };
+
+        Self {
+            distance,
+            metres,
+            brand,
+            name,
+            total: 0.5 * distance + 0.3 * brand + 0.2 * name,
+        }
+    }
+}
+
+impl fmt::Display for Score {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:.2} (distance {:.0}m {:.2}, brand {:.2}, name {:.2})",
+            self.total, self.metres, self.distance, self.brand, self.name
+        )
+    }
+}
+
+fn expected_brand(vendor: Vendor, name: &str) -> Option<&'static str> {
+    let name = name.to_lowercase();
+    BRANDS
+        .iter()
+        .find(|(v, prefix, _)| *v == vendor && name.starts_with(prefix))
+        .map(|(_, _, id)| *id)
+}
+
+// e.g. `karrinyup` for "Coles Karrinyup"
+fn branch(name: &str) -> String {
+    let name = name.to_lowercase();
+    name.split(|c: char| !c.is_alphanumeric())
+        .filter(|x| {
+            !x.is_empty()
+                && !BRANDS
+                    .iter()
+                    .any(|(_, prefix, _)| prefix.split(' ').any(|y| y == *x))
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// the old `geo::Point` layout, with the latitude in x
 #[derive(Deserialize)]
 struct RawStore {
     id: u32,
-    #[serde(flatten)]
-    point: Point,
+    x: f64,
+    y: f64,
+    // e.g. "Coles Local Double Bay"
+    #[serde(default)]
+    name: Option<String>,
+}
+
+impl RawStore {
+    fn position(&self) -> LatLon {
+        LatLon::new(self.x, self.y)
+    }
 }
 
 #[derive(Deserialize, Serialize)]
@@ -202,3 +385,69 @@ struct DataDump {
     id: StoreId,
     osm: OsmId,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn elem(id: u64, wikidata: &str, branch: &str) -> OsmElement {
+        let tags = [("brand:wikidata", wikidata), ("branch", branch)];
+        crate::stores::overpass::elem(id, -33.8766, &tags).1
+    }
+
+    #[test]
+    fn prefers_same_format() {
+        let store = RawStore {
+            id: 1,
+            x: -33.8766,
+            y: 151.2400,
+            name: Some("Coles Local Double Bay".to_string()),
+        };
+        let supermarket = Score::new(
+            Vendor::Coles,
+            &store,
+            &elem(10, "Q1108172", "Double Bay"),
+            20.0,
+        );
+        let local = Score::new(
+            Vendor::Coles,
+            &store,
+            &elem(11, "Q104850818", "Double Bay"),
+            80.0,
+        );
+        assert_eq!((supermarket.brand, supermarket.name), (0.0, 1.0));
+        assert_eq!((local.brand, local.name), (1.0, 1.0));
+        assert!(local.total > supermarket.total);
+
+        assert_eq!(branch("Woolworths Metro Bondi Beach"), "bondi beach");
+        assert_eq!(
+            expected_brand(Vendor::Woolworths, "Woolworths Metro Bondi Beach"),
+            Some("Q111772555")
+        );
+        assert_eq!(expected_brand(Vendor::Coles, "Woolworths Bondi"), None);
+    }
+
+    #[test]
+    fn keeps_unchanged_matches() {
+        let mut output = BTreeMap::from([(StoreId::Coles(1), OsmId::Node(100010))]);
+        let kept = BTreeMap::from([
+            // overridden since
+            (StoreId::Coles(1), OsmId::Node(100011)),
+            (StoreId::Coles(2), OsmId::Node(100010)),
+            (StoreId::Coles(3), OsmId::Node(100030)),
+            (StoreId::Coles(4), OsmId::Node(100040)),
+            (StoreId::Coles(5), OsmId::Node(100050)),
+        ]);
+        let ignored = BTreeSet::from([StoreId::Coles(4)]);
+        let never = BTreeSet::from([(StoreId::Coles(5), OsmId::Node(100050))]);
+
+        assert_eq!(keep(&mut output, kept, &ignored, &never), 1);
+        assert_eq!(
+            output,
+            BTreeMap::from([
+                (StoreId::Coles(1), OsmId::Node(100010)),
+                (StoreId::Coles(3), OsmId::Node(100030)),
+            ])
+        );
+    }
+}
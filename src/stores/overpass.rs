@@ -1,17 +1,19 @@
 use std::collections::BTreeMap;
 
 use anyhow::Result;
-use geo::Point;
 use serde::{Deserialize, Serialize};
 
-use crate::{agent, OsmId};
+use crate::OsmId;
+
+use super::{
+    http::{client, Service},
+    models::LatLon,
+};
 
 pub fn query(q: &str) -> Result<Vec<OsmElement>> {
     let payload = format!("[out:json][timeout:25]; {q}");
-    let response: OverpassResponse = agent()
-        .post("https://overpass-api.de/api/interpreter")
-        .send_form(&[("data", &payload)])?
-        .into_json()?;
+    let response: OverpassResponse =
+        client().post_form(Service::Overpass, "/interpreter", &[("data", &payload)])?;
 
     Ok(response
         .elements
@@ -31,17 +33,17 @@ pub enum RawElement {
     Node {
         id: u64,
         #[serde(flatten)]
-        center: RawPosition,
+        center: LatLon,
         tags: BTreeMap<String, String>,
     },
     Way {
         id: u64,
-        center: RawPosition,
+        center: LatLon,
         tags: BTreeMap<String, String>,
     },
     Relation {
         id: u64,
-        center: RawPosition,
+        center: LatLon,
         tags: BTreeMap<String, String>,
     },
 }
@@ -51,39 +53,42 @@ impl RawElement {
         match self {
             Self::Node { id, center, tags } => OsmElement {
                 id: OsmId::Node(id),
-                point: center.simplify(),
+                position: center,
                 tags,
             },
             Self::Way { id, center, tags } => OsmElement {
                 id: OsmId::Way(id),
-                point: center.simplify(),
+                position: center,
                 tags,
             },
             Self::Relation { id, center, tags } => OsmElement {
                 id: OsmId::Relation(id),
-                point: center.simplify(),
+                position: center,
                 tags,
             },
         }
     }
 }
 
-#[derive(Deserialize)]
-pub struct RawPosition {
-    lat: f64,
-    lon: f64,
-}
-
-impl RawPosition {
-    fn simplify(self) -> Point {
-        Point::new(self.lat, self.lon)
-    }
-}
-
 #[derive(Deserialize, Serialize)]
 pub struct OsmElement {
     pub id: OsmId,
     #[serde(flatten)]
-    pub point: Point,
+    pub position: LatLon,
     pub tags: BTreeMap<String, String>,
 }
+
+/// A node near Sydney for tests, keyed for collecting into a map.
+#[cfg(test)]
+pub fn elem(id: u64, lat: f64, tags: &[(&str, &str)]) -> (OsmId, OsmElement) {
+    let id = OsmId::Node(id);
+    let elem = OsmElement {
+        id,
+        position: LatLon::new(lat, 151.2),
+        tags: tags
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect(),
+    };
+    (id, elem)
+}
@@ -0,0 +1,183 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+use crate::{OsmId, Vendor};
+
+use super::{models::StoreId, overpass::OsmElement};
+
+/// Hand-maintained corrections to store conflation, e.g.
+///
+/// ```yaml
+/// match:
+///   - { store: 7681, osm: n11932337694 }
+/// never:
+///   - { store: 779, osm: w1286477920 }
+/// ignore: [596, 749]
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Overrides {
+    /// used whatever the distance
+    #[serde(default, rename = "match")]
+    pub force: Vec<Pair>,
+    #[serde(default)]
+    pub never: Vec<Pair>,
+    /// e.g. stores that have closed
+    #[serde(default)]
+    pub ignore: Vec<u32>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Pair {
+    pub store: u32,
+    pub osm: OsmId,
+}
+
+pub fn path(vendor: Vendor) -> PathBuf {
+    PathBuf::from(format!("data/stores/overrides-{}.yaml", vendor.slug()))
+}
+
+impl Overrides {
+    pub fn load(vendor: Vendor) -> Result<Self> {
+        let path = path(vendor);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        Self::parse(&fs::read_to_string(&path)?)
+            .with_context(|| format!("Failed to load {}", path.display()))
+    }
+
+    pub fn parse(yaml: &str) -> Result<Self> {
+        Ok(serde_yaml::from_str::<Option<Self>>(yaml)?.unwrap_or_default())
+    }
+
+    pub fn ignored(&self, vendor: Vendor) -> BTreeSet<StoreId> {
+        self.ignore
+            .iter()
+            .map(|x| StoreId::new(vendor, *x))
+            .collect()
+    }
+
+    pub fn never(&self, vendor: Vendor) -> BTreeSet<(StoreId, OsmId)> {
+        self.never
+            .iter()
+            .map(|x| (StoreId::new(vendor, x.store), x.osm))
+            .collect()
+    }
+
+    /// The matches automatic matching must keep, with overrides winning over
+    /// `website` tags, and every conflict found.
+    pub fn apply(
+        &self,
+        vendor: Vendor,
+        website: &BTreeMap<StoreId, OsmId>,
+        osm_elems: &BTreeMap<OsmId, OsmElement>,
+    ) -> (BTreeMap<StoreId, OsmId>, Vec<String>) {
+        let ignored = self.ignored(vendor);
+        let never = self.never(vendor);
+        let mut conflicts = Vec::new();
+
+        let mut output = BTreeMap::new();
+        for (store, osm) in website {
+            if ignored.contains(store) {
+                conflicts.push(format!("{store:?} is ignored but {osm:?} links to it"));
+            } else if never.contains(&(*store, *osm)) {
+                conflicts.push(format!(
+                    "{store:?} must never match {osm:?} but its website links to it"
+                ));
+            } else {
+                output.insert(*store, *osm);
+            }
+        }
+
+        for x in &self.force {
+            let (store, osm) = (StoreId::new(vendor, x.store), x.osm);
+            if ignored.contains(&store) || never.contains(&(store, osm)) {
+                conflicts.push(format!(
+                    "{store:?} is forced to match {osm:?} but also ignored or never matched"
+                ));
+                continue;
+            }
+            if !osm_elems.contains_key(&osm) {
+                conflicts.push(format!("{store:?} is forced to match unknown {osm:?}"));
+                continue;
+            }
+            if let Some(other) = website.get(&store).filter(|x| **x != osm) {
+                conflicts.push(format!(
+                    "{store:?} is forced to match {osm:?} but {other:?} links to it"
+                ));
+            }
+            for (other, _) in website.iter().filter(|(k, v)| **v == osm && **k != store) {
+                conflicts.push(format!(
+                    "{store:?} is forced to match {osm:?} but its website links to {other:?}"
+                ));
+            }
+            output.retain(|_, v| *v != osm);
+            output.insert(store, osm);
+        }
+
+        (output, conflicts)
+    }
+}
+
+pub fn modified_since(vendor: Vendor, cached: &Path) -> Result<bool> {
+    let overrides = path(vendor);
+    if !overrides.exists() || !cached.exists() {
+        return Ok(false);
+    }
+    Ok(fs::metadata(overrides)?.modified()? > fs::metadata(cached)?.modified()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn elem(id: u64) -> (OsmId, OsmElement) {
+        crate::stores::overpass::elem(id, -33.8, &[])
+    }
+
+    #[test]
+    fn apply() {
+        let overrides = Overrides::parse(
+            r#"
+match:
+  - { store: 1, osm: n100010 }
+  - { store: 2, osm: n100099 }
+never:
+  - { store: 3, osm: n100030 }
+ignore: [4]
+"#,
+        )
+        .unwrap();
+        let website = BTreeMap::from([
+            // the element forced to store 1
+            (StoreId::Coles(5), OsmId::Node(100010)),
+            (StoreId::Coles(3), OsmId::Node(100030)),
+            (StoreId::Coles(4), OsmId::Node(100040)),
+            (StoreId::Coles(6), OsmId::Node(100060)),
+        ]);
+        let osm_elems = BTreeMap::from([elem(100010), elem(100030), elem(100040), elem(100060)]);
+
+        let (output, conflicts) = overrides.apply(Vendor::Coles, &website, &osm_elems);
+        assert_eq!(
+            output,
+            BTreeMap::from([
+                (StoreId::Coles(1), OsmId::Node(100010)),
+                (StoreId::Coles(6), OsmId::Node(100060)),
+            ])
+        );
+        assert_eq!(conflicts.len(), 4);
+        assert!(conflicts.iter().any(|x| x.contains("is ignored")));
+        assert!(conflicts.iter().any(|x| x.contains("unknown")));
+
+        assert!(Overrides::parse("").unwrap().force.is_empty());
+        assert!(Overrides::parse("pin: []").is_err());
+    }
+}
@@ -0,0 +1,260 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Write,
+    fs,
+    path::Path,
+};
+
+use anyhow::Result;
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{OsmId, Vendor};
+
+use super::{gnaf_addrs::GNAFAddress, osm_elems, osm_ids, overpass::OsmElement};
+
+// metres, as the centre of a way shifts slightly with unrelated edits
+const MOVED_DISTANCE: f64 = 20.0;
+const MATCH_TAGS: [&str; 4] = ["brand:wikidata", "website", "branch", "name"];
+
+/// Re-queries OSM, reports what changed since the cache was written, and
+/// drops cached addresses and areas of changed elements so only those are
+/// fetched again by the build. Stores are matched again, keeping matches to
+/// elements that didn't change.
+pub fn main() -> Result<()> {
+    for vendor in Vendor::all() {
+        let slug = vendor.slug();
+        // without a cache every element is new
+        let cached = osm_elems::cached(vendor)?.unwrap_or_default();
+        let fresh = osm_elems::fetch(vendor)?;
+        let diff = Diff::new(&cached, &fresh);
+
+        let md_path = format!("data/stores/refresh-{slug}.md");
+        fs::write(&md_path, diff.report(&cached, &fresh)?)?;
+        eprintln!(
+            "{vendor}: {} new, {} removed, {} moved, {} retagged, see {md_path}",
+            diff.new.len(),
+            diff.removed.len(),
+            diff.moved.len(),
+            diff.retagged.len()
+        );
+        if diff.is_empty() {
+            continue;
+        }
+
+        // positions feed reverse geocoding and areas, tags feed Nominatim
+        let moved: BTreeSet<OsmId> = diff
+            .removed
+            .iter()
+            .chain(diff.moved.keys())
+            .copied()
+            .collect();
+        let mut changed = moved.clone();
+        changed.extend(diff.retagged.keys());
+        prune::<GNAFAddress>(&format!("data/stores/gnaf-addrs-{slug}.json"), &moved)?;
+        prune::<Vec<OsmElement>>(&format!("data/stores/osm-areas-{slug}.json"), &moved)?;
+        prune::<BTreeMap<String, String>>(&format!("data/stores/osm-addrs-{slug}.json"), &changed)?;
+
+        osm_elems::write(vendor, &fresh)?;
+        if diff.rematch() {
+            osm_ids::rematch(vendor, &fresh, &diff.stale())?;
+        }
+    }
+
+    super::build()
+}
+
+#[derive(Debug, Default)]
+struct Diff {
+    new: BTreeSet<OsmId>,
+    removed: BTreeSet<OsmId>,
+    /// metres
+    moved: BTreeMap<OsmId, f64>,
+    retagged: BTreeMap<OsmId, BTreeSet<String>>,
+}
+
+impl Diff {
+    fn new(cached: &BTreeMap<OsmId, OsmElement>, fresh: &BTreeMap<OsmId, OsmElement>) -> Self {
+        let mut output = Self::default();
+        for (id, x) in fresh {
+            let Some(old) = cached.get(id) else {
+                output.new.insert(*id);
+                continue;
+            };
+            let distance = old.position.distance(&x.position);
+            if distance >= MOVED_DISTANCE {
+                output.moved.insert(*id, distance);
+            }
+            let tags: BTreeSet<String> = old
+                .tags
+                .keys()
+                .chain(x.tags.keys())
+                .filter(|k| old.tags.get(*k) != x.tags.get(*k))
+                .cloned()
+                .collect();
+            if !tags.is_empty() {
+                output.retagged.insert(*id, tags);
+            }
+        }
+        output.removed = cached
+            .keys()
+            .filter(|x| !fresh.contains_key(x))
+            .copied()
+            .collect();
+        output
+    }
+
+    fn is_empty(&self) -> bool {
+        self.new.is_empty()
+            && self.removed.is_empty()
+            && self.moved.is_empty()
+            && self.retagged.is_empty()
+    }
+
+    // matches to these may no longer be right
+    fn stale(&self) -> BTreeSet<OsmId> {
+        let retagged = self
+            .retagged
+            .iter()
+            .filter(|(_, tags)| tags.iter().any(|k| MATCH_TAGS.contains(&k.as_str())))
+            .map(|(id, _)| id);
+        self.removed
+            .iter()
+            .chain(self.moved.keys())
+            .chain(retagged)
+            .copied()
+            .collect()
+    }
+
+    fn rematch(&self) -> bool {
+        !self.new.is_empty() || !self.stale().is_empty()
+    }
+
+    fn report(
+        &self,
+        cached: &BTreeMap<OsmId, OsmElement>,
+        fresh: &BTreeMap<OsmId, OsmElement>,
+    ) -> Result<String> {
+        let name = |x: &OsmElement| {
+            let name = x.tags.get("name").map_or("", String::as_str);
+            match x.tags.get("branch") {
+                Some(branch) => format!("{name} {branch}"),
+                None => name.to_string(),
+            }
+        };
+
+        let mut md = String::new();
+        writeln!(md, "## Statistics\n")?;
+        writeln!(md, "- {} cached, {} fetched", cached.len(), fresh.len())?;
+        writeln!(md, "- {} new", self.new.len())?;
+        writeln!(md, "- {} removed", self.removed.len())?;
+        writeln!(md, "- {} moved", self.moved.len())?;
+        writeln!(md, "- {} retagged", self.retagged.len())?;
+        writeln!(md)?;
+        if self.is_empty() {
+            return Ok(md);
+        }
+
+        writeln!(md, "## Changes\n")?;
+        for x in &self.new {
+            writeln!(md, "- new {x:?} {}", name(&fresh[x]))?;
+        }
+        for x in &self.removed {
+            writeln!(md, "- removed {x:?} {}", name(&cached[x]))?;
+        }
+        for (x, d) in &self.moved {
+            writeln!(md, "- moved {x:?} {} by {d:.0}m", name(&fresh[x]))?;
+        }
+        for (x, tags) in &self.retagged {
+            let tags: Vec<&str> = tags.iter().map(String::as_str).collect();
+            writeln!(
+                md,
+                "- retagged {x:?} {}: {}",
+                name(&fresh[x]),
+                tags.join(", ")
+            )?;
+        }
+        writeln!(md)?;
+        Ok(md)
+    }
+}
+
+fn prune<T: Serialize + DeserializeOwned>(path: &str, ids: &BTreeSet<OsmId>) -> Result<()> {
+    if !Path::new(path).exists() {
+        return Ok(());
+    }
+    let mut raw: BTreeMap<OsmId, T> = serde_json::from_str(&fs::read_to_string(path)?)?;
+    let before = raw.len();
+    raw.retain(|k, _| !ids.contains(k));
+    if raw.len() != before {
+        fs::write(path, serde_json::to_string_pretty(&raw)?)?;
+        eprintln!("Dropped {} stale entries from {path}", before - raw.len());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stores::overpass::elem;
+
+    #[test]
+    fn diff() {
+        let cached = BTreeMap::from([
+            elem(100001, -33.8, &[("name", "Coles")]),
+            elem(100002, -33.8, &[("name", "Coles")]),
+            elem(100003, -33.8, &[("name", "Coles")]),
+            elem(100004, -33.8, &[("name", "Coles")]),
+        ]);
+        let fresh = BTreeMap::from([
+            elem(100001, -33.8, &[("name", "Coles")]),
+            // about 11m
+            elem(100002, -33.8001, &[("name", "Coles")]),
+            // about 111m
+            elem(100003, -33.801, &[("name", "Coles")]),
+            elem(
+                100004,
+                -33.8,
+                &[("name", "Coles"), ("opening_hours", "24/7")],
+            ),
+            elem(100005, -33.9, &[("name", "Coles Local")]),
+        ]);
+
+        let diff = Diff::new(&cached, &fresh);
+        assert_eq!(diff.new, BTreeSet::from([OsmId::Node(100005)]));
+        assert!(diff.removed.is_empty());
+        assert_eq!(
+            diff.moved.keys().collect::<Vec<_>>(),
+            [&OsmId::Node(100003)]
+        );
+        assert_eq!(
+            diff.retagged[&OsmId::Node(100004)],
+            BTreeSet::from(["opening_hours".to_string()])
+        );
+        assert_eq!(diff.stale(), BTreeSet::from([OsmId::Node(100003)]));
+        assert!(diff.rematch());
+
+        // two elements removed and one retagged with opening_hours, which
+        // doesn't affect matching, so only the removed are stale
+        let fresh = BTreeMap::from([
+            elem(100001, -33.8, &[("name", "Coles")]),
+            elem(
+                100002,
+                -33.8,
+                &[("name", "Coles"), ("opening_hours", "24/7")],
+            ),
+        ]);
+        let diff = Diff::new(&cached, &fresh);
+        assert_eq!(diff.removed.len(), 2);
+        assert_eq!(diff.retagged.len(), 1);
+        assert_eq!(
+            diff.stale(),
+            BTreeSet::from([OsmId::Node(100003), OsmId::Node(100004)])
+        );
+        assert!(diff.rematch());
+        let diff = Diff {
+            removed: BTreeSet::new(),
+            ..diff
+        };
+        assert!(!diff.rematch());
+    }
+}
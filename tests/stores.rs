@@ -0,0 +1,44 @@
+use std::{fs, path::Path, process::Command};
+
+const FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/stores");
+
+fn copy_dir(from: &Path, to: &Path) {
+    fs::create_dir_all(to).unwrap();
+    for x in fs::read_dir(from).unwrap() {
+        let x = x.unwrap();
+        if x.file_type().unwrap().is_dir() {
+            copy_dir(&x.path(), &to.join(x.file_name()));
+        } else {
+            fs::copy(x.path(), to.join(x.file_name())).unwrap();
+        }
+    }
+}
+
+/// Builds two stores per vendor from empty caches, with every request
+/// answered by fixtures recorded from a stand-in server.
+#[test]
+fn build_from_fixtures() {
+    let dir = std::env::temp_dir().join(format!("stores-replay-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    copy_dir(&Path::new(FIXTURES).join("internal"), &dir.join("internal"));
+    fs::create_dir_all(dir.join("data/stores")).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_database"))
+        .args(["stores", "--replay", FIXTURES])
+        .current_dir(&dir)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let expected = fs::read_to_string(Path::new(FIXTURES).join("output.json")).unwrap();
+    let actual = fs::read_to_string(dir.join("data/stores/output.json")).unwrap();
+    assert_eq!(actual, expected);
+    assert!(dir.join("data/stores/output.geojson").exists());
+    assert!(dir.join("data/stores/output.fgb").exists());
+
+    fs::remove_dir_all(&dir).unwrap();
+}